@@ -4,49 +4,245 @@
  * 
  * (c) 2024 Andreas Feldner
  */
-
 use dateparser::DateTimeUtc;
 use minidom::Element;
+use percent_encoding::percent_decode_str;
 use url::Url;
+use crate::filter::Filterable;
 
 #[derive(Debug)]
 pub struct CatalogueInfo {
     pub url: Url,
     pub name: String,
+    /// The DAV displayname, which servers may set to a more readable name
+    pub display_name: Option<String>,
     pub size: Option<u64>,
     pub date: Option<DateTimeUtc>,
-    pub file_type: Option<String>
+    pub created: Option<DateTimeUtc>,
+    pub file_type: Option<String>,
+    pub file_type_params: Vec<(String, String)>,
+    pub is_collection: bool,
+    pub etag: Option<String>,
+    /// The RFC 4331 quota of a collection, if asked for and reported
+    pub quota_used_bytes: Option<u64>,
+    pub quota_available_bytes: Option<u64>
 }
 
 macro_rules! extract_property {
-    ($target:expr, $element_name:expr, $namespace_name:expr, $prop:expr) => {
-        $target = match $prop.get_child($element_name, $namespace_name) {
-            Some(size_node) => match size_node.text().as_str().parse() {
-                Ok(size) => Some(size),
-                Err(_) => None
-            },
-            None => None
-        };
+    ($target:expr, $element_name:expr, $namespace_name:expr, $prop:expr, $href:expr) => {
+        // keep what an earlier propstat delivered, if this one lacks the property
+        if let Some(property_node) = $prop.get_child($element_name, $namespace_name) {
+            let text = property_node.text();
+            $target = match text.trim().parse() {
+                Ok(value) => Some(value),
+                Err(_) => {
+                    // present, but not usable: don't let it vanish silently
+                    log::warn!("ignoring malformed {} '{}' of {}", $element_name, text.trim(), $href);
+                    None
+                }
+            };
+        }
     }
 }
 
 impl CatalogueInfo {
     pub fn new(base: &Url, response: &Element) -> CatalogueInfo {
-        let mut info = CatalogueInfo {url: base.to_owned(), name: String::from(""), size: None, date: None, file_type: None};
-        info.name = match response.get_child("href", "DAV:") {
+        let mut info = CatalogueInfo {url: base.to_owned(), name: String::from(""), display_name: None, size: None, date: None, created: None, file_type: None, file_type_params: Vec::new(),
+            is_collection: false, etag: None, quota_used_bytes: None, quota_available_bytes: None};
+        let href = match response.get_child("href", "DAV:") {
             Some(href_child) => href_child.text(),
             None => String::from(".")
         };
-        if let Ok(joined_url) = base.join(&info.name) {
+        // join the href as delivered (i.e. encoded), the decoded name would re-interpret '#', '?' and '%'
+        if let Ok(joined_url) = base.join(&Self::_encode_href(&href)) {
             info.url = joined_url;
         }
-        if let Some(propstat) = response.get_child("propstat", "DAV:") {
+        info.name = Self::_name_from_href(&href);
+        // there is one propstat per status; properties in a failed one (typically 404) aren't there
+        for propstat in response.children().filter(|child| child.is("propstat", "DAV:") && Self::is_successful_propstat(child)) {
             if let Some(prop) = propstat.get_child("prop", "DAV:") {
-                extract_property!(info.size, "getcontentlength", "DAV:", prop);
-                extract_property!(info.date, "getlastmodified", "DAV:", prop);
-                extract_property!(info.file_type, "getcontenttype", "DAV:", prop);
+                extract_property!(info.size, "getcontentlength", "DAV:", prop, href);
+                extract_property!(info.date, "getlastmodified", "DAV:", prop, href);
+                extract_property!(info.created, "creationdate", "DAV:", prop, href);
+                extract_property!(info.display_name, "displayname", "DAV:", prop, href);
+                extract_property!(info.quota_used_bytes, "quota-used-bytes", "DAV:", prop, href);
+                extract_property!(info.quota_available_bytes, "quota-available-bytes", "DAV:", prop, href);
+                if let Some(resource_type) = prop.get_child("resourcetype", "DAV:") {
+                    info.is_collection = resource_type.get_child("collection", "DAV:").is_some();
+                }
+                if let Some(etag_node) = prop.get_child("getetag", "DAV:") {
+                    info.etag = Some(etag_node.text().trim().to_string());
+                }
+                if let Some(type_node) = prop.get_child("getcontenttype", "DAV:") {
+                    let (base_type, params) = Self::_split_content_type(&type_node.text());
+                    info.file_type = Some(base_type);
+                    info.file_type_params = params;
+                }
             }
         }
+        // servers tend to send an empty displayname rather than none
+        info.display_name = info.display_name.filter(|display_name: &String| !display_name.is_empty());
         info
     }
+
+    /// Checks the status of a propstat, like `HTTP/1.1 200 OK`. A missing
+    /// or unparseable status is taken as success.
+    pub(crate) fn is_successful_propstat(propstat: &Element) -> bool {
+        propstat.get_child("status", "DAV:")
+            .and_then(|status| status.text().split_whitespace().nth(1).and_then(|code| code.parse::<u16>().ok()))
+            .is_none_or(|code| (200..300).contains(&code))
+    }
+
+    /// Escapes characters that some servers leave unencoded in an href,
+    /// but that would change its meaning when parsed as a URL.
+    fn _encode_href(href: &str) -> String {
+        let bytes = href.as_bytes();
+        let mut encoded = String::with_capacity(href.len());
+        for (pos, character) in href.char_indices() {
+            match character {
+                '#' => encoded.push_str("%23"),
+                '?' => encoded.push_str("%3F"),
+                ' ' => encoded.push_str("%20"),
+                '%' if !(bytes.len() > pos + 2
+                         && bytes[pos + 1].is_ascii_hexdigit()
+                         && bytes[pos + 2].is_ascii_hexdigit()) => encoded.push_str("%25"),
+                _ => encoded.push(character)
+            }
+        }
+        encoded
+    }
+
+    /// Derives the human readable name of an entry, i.e. the decoded last
+    /// path segment of its href.
+    fn _name_from_href(href: &str) -> String {
+        let last_segment = href.trim_end_matches('/').rsplit('/').next().unwrap_or(href);
+        if last_segment.is_empty() {
+            return String::from(".");
+        }
+        percent_decode_str(last_segment).decode_utf8_lossy().into_owned()
+    }
+
+    /// Splits a content type like `text/plain; charset="utf-8"` into its
+    /// base type and the list of its parameters.
+    fn _split_content_type(content_type: &str) -> (String, Vec<(String, String)>) {
+        let mut parts = content_type.split(';');
+        // split always yields at least one element
+        let base_type = parts.next().unwrap_or("").trim().to_string();
+        let params = parts.filter_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            Some((name.trim().to_string(), value.trim().trim_matches('"').to_string()))
+        }).collect();
+        (base_type, params)
+    }
+
+    /// Reconstructs the content type including its parameters, as
+    /// delivered by the server.
+    pub fn full_content_type(&self) -> Option<String> {
+        Filterable::full_content_type(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response_with_props(href: &str, props: &str) -> Element {
+        format!(r#"<response xmlns="DAV:"><href>{href}</href><propstat><prop>{props}</prop><status>HTTP/1.1 200 OK</status></propstat></response>"#)
+            .parse().unwrap()
+    }
+
+    #[test]
+    fn test_content_type_with_parameters () {
+        let base = Url::parse("https://example.com/dir/").unwrap();
+        let response = response_with_props("/dir/notes.txt", "<getcontenttype>text/plain; charset=\"utf-8\"</getcontenttype>");
+        let info = CatalogueInfo::new(&base, &response);
+        assert_eq!(info.file_type.as_deref(), Some("text/plain"));
+        assert_eq!(info.file_type_params, vec!(("charset".to_string(), "utf-8".to_string())));
+        assert_eq!(info.full_content_type().as_deref(), Some("text/plain; charset=utf-8"));
+    }
+
+    #[test]
+    fn test_href_with_reserved_characters () {
+        let base = Url::parse("https://example.com/dir/").unwrap();
+        let encoded = CatalogueInfo::new(&base, &response_with_props("/dir/a%23b%20c.txt", ""));
+        assert_eq!(encoded.url.as_str(), "https://example.com/dir/a%23b%20c.txt");
+        assert_eq!(encoded.name, "a#b c.txt");
+        // some servers do not encode the href properly
+        let unencoded = CatalogueInfo::new(&base, &response_with_props("/dir/50% off #1?.txt", ""));
+        assert_eq!(unencoded.url.as_str(), "https://example.com/dir/50%25%20off%20%231%3F.txt");
+        assert_eq!(unencoded.url.fragment(), None);
+        assert_eq!(unencoded.name, "50% off #1?.txt");
+        let collection = CatalogueInfo::new(&base, &response_with_props("/dir/sub%20dir/", ""));
+        assert_eq!(collection.url.as_str(), "https://example.com/dir/sub%20dir/");
+        assert_eq!(collection.name, "sub dir");
+    }
+
+    #[test]
+    fn test_creation_date_separate_from_modification () {
+        let base = Url::parse("https://example.com/dir/").unwrap();
+        let response = response_with_props("/dir/scan.pdf", 
+            "<creationdate>2019-03-01T08:00:00Z</creationdate><getlastmodified>Tue, 02 Jan 2024 10:00:00 GMT</getlastmodified>");
+        let info = CatalogueInfo::new(&base, &response);
+        assert_eq!(info.created.map(|DateTimeUtc(created)| created.to_rfc3339()).as_deref(), Some("2019-03-01T08:00:00+00:00"));
+        assert_eq!(info.date.map(|DateTimeUtc(modified)| modified.to_rfc3339()).as_deref(), Some("2024-01-02T10:00:00+00:00"));
+        let without = CatalogueInfo::new(&base, &response_with_props("/dir/other.pdf", ""));
+        assert!(without.created.is_none());
+    }
+
+    #[test]
+    fn test_display_name () {
+        let base = Url::parse("https://example.com/dir/").unwrap();
+        let named = CatalogueInfo::new(&base, &response_with_props("/dir/a1b2.pdf", "<displayname> Annual Report </displayname>"));
+        assert_eq!(named.display_name.as_deref(), Some("Annual Report"));
+        assert_eq!(named.name, "a1b2.pdf");
+        assert!(CatalogueInfo::new(&base, &response_with_props("/dir/a.pdf", "<displayname/>")).display_name.is_none());
+        assert!(CatalogueInfo::new(&base, &response_with_props("/dir/a.pdf", "")).display_name.is_none());
+    }
+
+    #[test]
+    fn test_failed_propstat_is_ignored () {
+        let base = Url::parse("https://example.com/dir/").unwrap();
+        let response: Element = r#"<response xmlns="DAV:"><href>/dir/notes.txt</href>
+            <propstat><prop><getcontentlength>42</getcontentlength></prop><status>HTTP/1.1 200 OK</status></propstat>
+            <propstat><prop><getcontenttype>bogus/type</getcontenttype><creationdate/></prop><status>HTTP/1.1 404 Not Found</status></propstat>
+            </response>"#.parse().unwrap();
+        let info = CatalogueInfo::new(&base, &response);
+        assert_eq!(info.size, Some(42));
+        assert_eq!(info.file_type, None);
+        // a minimal response only has the successful propstat
+        let minimal = CatalogueInfo::new(&base, &response_with_props("/dir/notes.txt", "<getcontentlength>42</getcontentlength>"));
+        assert_eq!(minimal.size, Some(42));
+    }
+
+    #[test]
+    fn test_collection_and_etag () {
+        let base = Url::parse("https://example.com/dir/").unwrap();
+        let collection = CatalogueInfo::new(&base, &response_with_props("/dir/sub/", "<resourcetype><collection/></resourcetype>"));
+        assert!(collection.is_collection);
+        let file = CatalogueInfo::new(&base, &response_with_props("/dir/a.txt", "<resourcetype/><getetag>\"abc\"</getetag>"));
+        assert!(!file.is_collection);
+        assert_eq!(file.etag.as_deref(), Some("\"abc\""));
+    }
+
+    #[test]
+    fn test_content_length_values () {
+        let base = Url::parse("https://example.com/dir/").unwrap();
+        let size_of = |length: &str| CatalogueInfo::new(&base,
+            &response_with_props("/dir/a.bin", &format!("<getcontentlength>{length}</getcontentlength>"))).size;
+        assert_eq!(size_of("42"), Some(42));
+        assert_eq!(size_of("\n  42 \n"), Some(42));
+        assert_eq!(size_of(""), None);
+        assert_eq!(size_of("unknown"), None);
+        assert_eq!(size_of("-1"), None);
+        assert_eq!(size_of("18446744073709551616"), None);
+    }
+
+    #[test]
+    fn test_content_type_without_parameters () {
+        let base = Url::parse("https://example.com/dir/").unwrap();
+        let response = response_with_props("/dir/image.png", "<getcontenttype>image/png</getcontenttype>");
+        let info = CatalogueInfo::new(&base, &response);
+        assert_eq!(info.file_type.as_deref(), Some("image/png"));
+        assert!(info.file_type_params.is_empty());
+    }
 }
\ No newline at end of file
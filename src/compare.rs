@@ -0,0 +1,122 @@
+/**
+ * Defines the comparison of two directory trees, e.g. to verify the
+ * replication between two DAV paths.
+ *
+ * (c) 2024 Andreas Feldner
+ */
+use std::collections::BTreeMap;
+use std::fmt;
+use dateparser::DateTimeUtc;
+use crate::davctrl::WalkEntry;
+
+/// A difference between two trees, by path relative to their roots.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Difference {
+    OnlyLeft(String),
+    OnlyRight(String),
+    /// Present in both, the list tells what differs
+    Differs(String, Vec<String>)
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::OnlyLeft(path) => write!(f, "< {path}"),
+            Difference::OnlyRight(path) => write!(f, "> {path}"),
+            Difference::Differs(path, details) => write!(f, "! {path}: {}", details.join(", "))
+        }
+    }
+}
+
+/// Compares two walks, ordered by path. Entries are compared by the
+/// properties both sides report: size, etag and modification time; of
+/// collections only the presence counts.
+pub fn compare(left: Vec<WalkEntry>, right: Vec<WalkEntry>) -> Vec<Difference> {
+    let mut right: BTreeMap<String, WalkEntry> = right.into_iter()
+        .map(|entry| (entry.relative_path.clone(), entry))
+        .collect();
+    let left: BTreeMap<String, WalkEntry> = left.into_iter()
+        .map(|entry| (entry.relative_path.clone(), entry))
+        .collect();
+    let mut differences = Vec::new();
+    for (path, left_entry) in &left {
+        match right.remove(path) {
+            None => differences.push(Difference::OnlyLeft(path.clone())),
+            Some(right_entry) => {
+                let details = _details(left_entry, &right_entry);
+                if !details.is_empty() {
+                    differences.push(Difference::Differs(path.clone(), details));
+                }
+            }
+        }
+    }
+    differences.extend(right.into_keys().map(Difference::OnlyRight));
+    differences.sort_by(|first, second| _path(first).cmp(_path(second)));
+    differences
+}
+
+fn _path(difference: &Difference) -> &str {
+    match difference {
+        Difference::OnlyLeft(path) | Difference::OnlyRight(path) | Difference::Differs(path, _) => path
+    }
+}
+
+fn _details(left: &WalkEntry, right: &WalkEntry) -> Vec<String> {
+    let (left, right) = (&left.info, &right.info);
+    if left.is_collection || right.is_collection {
+        return if left.is_collection == right.is_collection {
+            Vec::new()
+        } else {
+            vec!("collection on one side only".to_string())
+        };
+    }
+    let mut details = Vec::new();
+    if let (Some(left_size), Some(right_size)) = (left.size, right.size) {
+        if left_size != right_size {
+            details.push(format!("size {left_size} vs {right_size}"));
+        }
+    }
+    if let (Some(left_etag), Some(right_etag)) = (&left.etag, &right.etag) {
+        if left_etag != right_etag {
+            details.push(format!("etag {left_etag} vs {right_etag}"));
+        }
+    }
+    if let (Some(DateTimeUtc(left_date)), Some(DateTimeUtc(right_date))) = (&left.date, &right.date) {
+        if left_date != right_date {
+            details.push(format!("modified {} vs {}", left_date.to_rfc3339(), right_date.to_rfc3339()));
+        }
+    }
+    details
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalogue::CatalogueInfo;
+    use url::Url;
+
+    fn entry(path: &str, size: Option<u64>, etag: Option<&str>) -> WalkEntry {
+        let base = Url::parse("https://example.com/").unwrap();
+        let response = format!(r#"<response xmlns="DAV:"><href>/{path}</href></response>"#).parse().unwrap();
+        let mut info = CatalogueInfo::new(&base, &response);
+        info.size = size;
+        info.etag = etag.map(str::to_string);
+        info.is_collection = path.ends_with('/');
+        WalkEntry {relative_path: path.to_string(), info}
+    }
+
+    #[test]
+    fn test_compare () {
+        let left = vec!(entry("same.txt", Some(3), Some("a")), entry("only-left.txt", None, None),
+            entry("sub/", None, None), entry("size.txt", Some(3), None), entry("etag.txt", Some(3), Some("a")));
+        let right = vec!(entry("etag.txt", Some(3), Some("b")), entry("same.txt", Some(3), Some("a")),
+            entry("sub/", None, None), entry("size.txt", Some(4), Some("x")), entry("sub/only-right.txt", None, None));
+        let differences: Vec<String> = compare(left, right).iter().map(Difference::to_string).collect();
+        assert_eq!(differences, vec!(
+            "! etag.txt: etag a vs b",
+            "< only-left.txt",
+            "! size.txt: size 3 vs 4",
+            "> sub/only-right.txt"
+        ));
+    }
+}
@@ -0,0 +1,166 @@
+/**
+ * Defines the completion of remote paths in interactive sessions.
+ *
+ * (c) 2024 Andreas Feldner
+ */
+use std::cell::RefCell;
+use std::rc::Rc;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use url::Url;
+use crate::davctrl::{DavController, Depth};
+use crate::filter::FilterCriteria;
+use crate::interactive::takes_remote_paths;
+
+/// What an editor's helper needs to know about the session.
+pub trait SessionHelper: Helper {
+    /// Called before each prompt with the collection paths are resolved against.
+    fn set_base_url(&mut self, _base_url: Option<&Url>) {}
+}
+
+impl SessionHelper for () {}
+
+/// Completes remote paths by listing the collection they are in.
+pub struct PathCompleter {
+    dav_ctrl: Rc<RefCell<DavController>>,
+    base_url: Option<Url>
+}
+
+impl PathCompleter {
+    pub fn new(dav_ctrl: Rc<RefCell<DavController>>) -> PathCompleter {
+        PathCompleter {dav_ctrl, base_url: None}
+    }
+
+    /// The candidates for a partial path, empty if not connected or the
+    /// listing fails.
+    fn _candidates(&self, partial_path: &str) -> Vec<Pair> {
+        let (collection_path, prefix) = match partial_path.rsplit_once('/') {
+            Some((collection, prefix)) => (format!("{collection}/"), prefix),
+            None => (String::new(), partial_path)
+        };
+        let Some(collection_url) = self.base_url.as_ref().and_then(|base_url| base_url.join(&collection_path).ok()) else {
+            return Vec::new();
+        };
+        let Ok(listing) = self.dav_ctrl.borrow().ls(&collection_url, Depth::One, &FilterCriteria::match_all()) else {
+            return Vec::new();
+        };
+        listing.into_iter()
+            // the listing includes the collection itself
+            .filter(|attrs| attrs.url.path().trim_end_matches('/') != collection_url.path().trim_end_matches('/'))
+            .filter(|attrs| attrs.name.starts_with(prefix))
+            .map(|attrs| {
+                let display = if attrs.is_collection {format!("{}/", attrs.name)} else {attrs.name};
+                let replacement = format!("{collection_path}{display}").replace(' ', "\\ ");
+                Pair {display, replacement}
+            })
+            .collect()
+    }
+
+    /// Where the last word of `before` starts. Whitespace escaped by a
+    /// backslash belongs to the word, as the candidates escape spaces.
+    fn _word_start(before: &str) -> usize {
+        let mut start = 0;
+        let mut escaped = false;
+        for (index, character) in before.char_indices() {
+            if escaped {
+                escaped = false;
+            } else if character == '\\' {
+                escaped = true;
+            } else if character.is_whitespace() {
+                start = index + character.len_utf8();
+            }
+        }
+        start
+    }
+
+    /// The word without the backslashes escaping its characters.
+    fn _unescape(word: &str) -> String {
+        let mut unescaped = String::with_capacity(word.len());
+        let mut characters = word.chars();
+        while let Some(character) = characters.next() {
+            match character {
+                '\\' => unescaped.extend(characters.next()),
+                _ => unescaped.push(character)
+            }
+        }
+        unescaped
+    }
+}
+
+impl SessionHelper for PathCompleter {
+    fn set_base_url(&mut self, base_url: Option<&Url>) {
+        self.base_url = base_url.cloned();
+    }
+}
+
+impl Completer for PathCompleter {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let start = Self::_word_start(before);
+        let command = before.split_whitespace().next().unwrap_or_default();
+        let word = &before[start..];
+        // neither the command itself nor its options are paths
+        if start == 0 || word.starts_with('-') || !takes_remote_paths(command) {
+            return Ok((pos, Vec::new()));
+        }
+        Ok((start, self._candidates(&Self::_unescape(word))))
+    }
+}
+
+impl Hinter for PathCompleter {
+    type Hint = String;
+}
+
+impl Highlighter for PathCompleter {}
+
+impl Validator for PathCompleter {}
+
+impl Helper for PathCompleter {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use netrc::Netrc;
+    use rustyline::history::DefaultHistory;
+    use crate::testserver::{Reply, TestServer};
+
+    #[test]
+    fn test_complete_path () {
+        let server = TestServer::start(vec!(
+            Reply::listing(&[("/dir/", true), ("/dir/notes.txt", false), ("/dir/new%20files/", true), ("/dir/other.txt", false)]),
+            Reply::listing(&[("/dir/new%20files/", true), ("/dir/new%20files/new.txt", false), ("/dir/new%20files/old.txt", false)]),
+            Reply::status(404, "Not Found", &[], "")
+        ));
+        let mut completer = PathCompleter::new(Rc::new(RefCell::new(DavController::new(Netrc::default()))));
+        completer.set_base_url(Some(&server.url));
+        let history = DefaultHistory::new();
+        let context = Context::new(&history);
+        let (start, candidates) = completer.complete("get dir/n", 9, &context).unwrap();
+        assert_eq!(start, 4);
+        let replacements: Vec<&str> = candidates.iter().map(|candidate| candidate.replacement.as_str()).collect();
+        assert_eq!(replacements, vec!("dir/notes.txt", "dir/new\\ files/"));
+        // a second Tab within the collection just completed
+        let line = "get dir/new\\ files/n";
+        let (start, candidates) = completer.complete(line, line.len(), &context).unwrap();
+        assert_eq!(start, 4);
+        let replacements: Vec<&str> = candidates.iter().map(|candidate| candidate.replacement.as_str()).collect();
+        assert_eq!(replacements, vec!("dir/new\\ files/new.txt"));
+        assert!(completer.complete("ls missing/", 11, &context).unwrap().1.is_empty());
+        // no requests for the command word, options and commands without remote paths
+        assert!(completer.complete("ge", 2, &context).unwrap().1.is_empty());
+        assert!(completer.complete("ls --lo", 7, &context).unwrap().1.is_empty());
+        assert!(completer.complete("format j", 8, &context).unwrap().1.is_empty());
+        assert!(completer.complete("set-download-dir d", 18, &context).unwrap().1.is_empty());
+        for command in ["find", "ls-by-criteria", "delete-by-criteria", "mv-by-criteria", "mktree", "put"] {
+            assert!(takes_remote_paths(command), "for {command}");
+        }
+        let requests = server.finish();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[1].path, "/dir/new%20files/");
+    }
+}
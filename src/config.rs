@@ -0,0 +1,160 @@
+/**
+ * Reads the configuration files of corroded_dav_cli, currently the
+ * .netrc holding the credentials, and locates the command history.
+ *
+ * (c) 2024 Andreas Feldner
+ */
+use derive_more::Display;
+use netrc::{Netrc, Error as NetrcError};
+use std::env;
+use std::ffi::OsString;
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Display)]
+pub enum ConfigError {
+    #[display(fmt = "{} does not exist", "_0.display()")]
+    NotFound(PathBuf),
+    #[display(fmt = "cannot read {}: {}", "_0.display()", _1)]
+    Unreadable(PathBuf, IoError),
+    #[display(fmt = "{}, line {}: {}", "file.display()", line, reason)]
+    Invalid { file: PathBuf, line: usize, reason: String }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// The place of the .netrc file, if there is a home directory.
+pub fn default_netrc_path() -> Option<PathBuf> {
+    #[allow(deprecated)]
+    // honestly, I don't care where you have to place .netrc if you run this on cygwin under Windows
+    env::home_dir().map(|home| home.join(".netrc"))
+}
+
+/// Environment variable naming the .netrc to read instead of ~/.netrc
+pub const NETRC_ENV: &str = "NETRC";
+
+/// The .netrc given in the environment, which has to exist then, unlike
+/// the default one.
+pub fn explicit_netrc_path() -> Option<PathBuf> {
+    netrc_path_from(env::var_os(NETRC_ENV))
+}
+
+/// The .netrc named by the value of `NETRC`, which counts as unset if empty.
+fn netrc_path_from(value: Option<OsString>) -> Option<PathBuf> {
+    value.filter(|path| !path.is_empty()).map(PathBuf::from)
+}
+
+/// Environment variable overriding the place of the command history
+pub const HISTORY_ENV: &str = "CORRODED_DAV_HISTORY";
+
+/// Environment variable naming a PEM file with a root certificate to trust
+pub const CA_FILE_ENV: &str = "CORRODED_DAV_CA_FILE";
+
+/// Environment variable that, set to 1, turns off verifying TLS certificates
+pub const TLS_INSECURE_ENV: &str = "CORRODED_DAV_TLS_INSECURE";
+
+/// The place of the command history of interactive sessions, if there is
+/// a home directory or it is given in the environment.
+pub fn history_path() -> Option<PathBuf> {
+    #[allow(deprecated)]
+    history_path_from(env::var_os(HISTORY_ENV), env::home_dir())
+}
+
+/// The place of the command history given the value of the environment
+/// variable and the home directory.
+fn history_path_from(value: Option<OsString>, home: Option<PathBuf>) -> Option<PathBuf> {
+    match value {
+        Some(path) if !path.is_empty() => Some(PathBuf::from(path)),
+        _ => home.map(|home| home.join(".corroded_dav_history"))
+    }
+}
+
+/// Removes what editors add without being asked for, and what the
+/// parsers of configuration files may trip over: a leading byte order
+/// mark, carriage returns and trailing whitespace. Lines are kept, so
+/// that line numbers in errors still match the file.
+fn normalize_config_text(content: &str) -> String {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    content.replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .lines()
+        .map(|line| line.trim_end().to_string() + "\n")
+        .collect()
+}
+
+pub fn read_netrc(path: &Path) -> Result<Netrc, ConfigError> {
+    if !path.is_file() {
+        return Err(ConfigError::NotFound(path.to_path_buf()));
+    }
+    let content = std::fs::read_to_string(path).map_err(|e| ConfigError::Unreadable(path.to_path_buf(), e))?;
+    Netrc::parse(normalize_config_text(&content).as_bytes()).map_err(|e| match e {
+        NetrcError::Io(io_error) => ConfigError::Unreadable(path.to_path_buf(), io_error),
+        NetrcError::Parse(reason, line) => ConfigError::Invalid {file: path.to_path_buf(), line, reason}
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mktemp::Temp;
+
+    #[test]
+    fn test_read_invalid_netrc () {
+        let netrc_file = Temp::new_file().unwrap();
+        std::fs::write(&netrc_file, "machine example.com\n  login user\n  passwort secret\n").unwrap();
+        match read_netrc(&netrc_file) {
+            Err(ConfigError::Invalid {line, reason, ..}) => {
+                assert_eq!(line, 3);
+                assert!(reason.contains("passwort"), "reason is {reason}");
+            },
+            other => panic!("Unexpected result {other:?}")
+        }
+    }
+
+    #[test]
+    fn test_read_missing_netrc () {
+        let dir = Temp::new_dir().unwrap();
+        assert!(matches!(read_netrc(&dir.join(".netrc")), Err(ConfigError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_read_netrc () {
+        let netrc_file = Temp::new_file().unwrap();
+        std::fs::write(&netrc_file, "machine example.com login user password secret\n").unwrap();
+        let netrc = read_netrc(&netrc_file).unwrap();
+        assert_eq!(netrc.hosts[0].0, "example.com");
+        assert_eq!(netrc.hosts[0].1.password.as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn test_read_netrc_with_bom () {
+        let netrc_file = Temp::new_file().unwrap();
+        std::fs::write(&netrc_file, "\u{feff}machine example.com \r\n  login user\t\r\n  password secret  \r\n").unwrap();
+        let netrc = read_netrc(&netrc_file).unwrap();
+        assert_eq!(netrc.hosts[0].0, "example.com");
+        assert_eq!(netrc.hosts[0].1.login, "user");
+        assert_eq!(netrc.hosts[0].1.password.as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn test_history_path_from_environment () {
+        let home = Some(PathBuf::from("/home/user"));
+        assert_eq!(history_path_from(Some(OsString::from("/tmp/dav_history")), home.clone()), Some(PathBuf::from("/tmp/dav_history")));
+        assert_eq!(history_path_from(Some(OsString::new()), home.clone()), Some(PathBuf::from("/home/user/.corroded_dav_history")));
+        assert_eq!(history_path_from(None, home), Some(PathBuf::from("/home/user/.corroded_dav_history")));
+        assert_eq!(history_path_from(None, None), None);
+    }
+
+    #[test]
+    fn test_netrc_path_from_environment () {
+        assert_eq!(netrc_path_from(Some(OsString::from("/run/secrets/netrc"))), Some(PathBuf::from("/run/secrets/netrc")));
+        assert_eq!(netrc_path_from(Some(OsString::new())), None);
+        assert_eq!(netrc_path_from(None), None);
+    }
+
+    #[test]
+    fn test_normalize_config_text () {
+        assert_eq!(normalize_config_text("\u{feff}a \r\nb\rc\t"), "a\nb\nc\n");
+        assert_eq!(normalize_config_text(""), "");
+    }
+}
@@ -0,0 +1,390 @@
+/**
+ * Defines the Client object, a thin WebDAV client on top of
+ * reqwest's blocking client.
+ *
+ * It offers the same operations as rustydav's client, which it
+ * replaces, but gives control over the request details that rustydav
+ * keeps hidden, like the redirect policy.
+ *
+ * (c) 2024 Andreas Feldner
+ */
+use reqwest::blocking::{Body, Client as HttpClient, Request, RequestBuilder, Response};
+use reqwest::{header, redirect, Certificate, Error, Method, NoProxy, Proxy, Url};
+use minidom::{Element, Error as DomError};
+use log::{log, warn, Level};
+use std::time::Duration;
+use std::io::{Error as IoError, ErrorKind};
+
+/// Maximum number of redirects followed for GET requests
+const MAX_REDIRECTS: usize = 10;
+
+/// The response headers shown with the status, for following what happened
+const TRACED_RESPONSE_HEADERS: [header::HeaderName; 5] = [header::CONTENT_LENGTH, header::CONTENT_TYPE,
+    header::ETAG, header::LAST_MODIFIED, header::LOCATION];
+
+/// The Accept header of GET requests not asking for a representation
+pub const ACCEPT_ANY: &str = "*/*";
+
+/// How long a request may wait for the server by default, the same as
+/// reqwest's default
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The proxy requests are sent through.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ProxySetting {
+    /// The proxies in `HTTP_PROXY`, `HTTPS_PROXY` and `ALL_PROXY`, except
+    /// for the hosts in `NO_PROXY`
+    #[default]
+    FromEnvironment,
+    /// No proxy at all, whatever the environment says
+    Direct,
+    /// This proxy for all requests, except for the hosts in `NO_PROXY`.
+    /// Credentials are taken from the URL.
+    Url(Url)
+}
+
+impl ProxySetting {
+    /// The URL of a proxy for display, without the password.
+    pub fn display_url(proxy_url: &Url) -> String {
+        let mut shown = proxy_url.clone();
+        if shown.password().is_some() {
+            let _ = shown.set_password(Some("***"));
+        }
+        shown.to_string()
+    }
+}
+
+/// Settings of the connections a client opens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientOptions {
+    /// How long to wait for connecting, and for each read and write;
+    /// `None` waits forever
+    pub timeout: Option<Duration>,
+    pub proxy: ProxySetting,
+    /// Shows each request and response as info instead of debug messages
+    pub verbose: bool,
+    /// A root certificate in PEM format trusted in addition to the
+    /// system's, e.g. for a server with a self-signed certificate
+    pub ca_certificate: Option<Vec<u8>>,
+    /// Accepts any certificate, so connections can be intercepted
+    pub accept_invalid_certs: bool
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {timeout: Some(DEFAULT_TIMEOUT), proxy: ProxySetting::default(), verbose: false,
+            ca_certificate: None, accept_invalid_certs: false}
+    }
+}
+
+/// A condition on the current state of a resource an upload depends on.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Precondition {
+    #[default]
+    None,
+    /// The resource still has this ETag, i.e. wasn't changed meanwhile
+    IfMatch(String),
+    /// The resource doesn't exist yet
+    IfNoneMatch
+}
+
+/// How a client authenticates with each request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Credentials {
+    /// Basic authentication, like from a netrc entry
+    Basic {username: String, password: String},
+    /// An `Authorization: Bearer` header, e.g. with an OAuth access token
+    Bearer(String)
+}
+
+/// Clones share the connection pool.
+#[derive(Debug, Clone)]
+pub struct Client {
+    credentials: Credentials,
+    client: HttpClient,
+    verbose: bool
+}
+
+impl Client {
+    pub fn init(username: &str, password: &str) -> Result<Self, Error> {
+        let credentials = Credentials::Basic {username: username.to_owned(), password: password.to_owned()};
+        Self::init_with(credentials, &ClientOptions::default())
+    }
+
+    /// Fails if the TLS backend cannot be initialised, e.g. with the
+    /// certificates given.
+    pub fn init_with(credentials: Credentials, options: &ClientOptions) -> Result<Self, Error> {
+        // Redirects are not followed automatically, because reqwest turns
+        // a redirected PROPFIND (or PUT, DELETE...) into a GET.
+        let builder = HttpClient::builder()
+            .redirect(redirect::Policy::none())
+            .timeout(options.timeout);
+        // reqwest uses the proxies of the environment, unless told otherwise
+        let builder = match &options.proxy {
+            ProxySetting::FromEnvironment => builder,
+            ProxySetting::Direct => builder.no_proxy(),
+            ProxySetting::Url(proxy_url) => match Proxy::all(proxy_url.clone()) {
+                Ok(proxy) => builder.proxy(proxy.no_proxy(NoProxy::from_env())),
+                Err(error) => {
+                    warn!("ignoring proxy {}: {error}", ProxySetting::display_url(proxy_url));
+                    builder
+                }
+            }
+        };
+        let builder = match options.ca_certificate.as_deref().map(Certificate::from_pem) {
+            None => builder,
+            Some(Ok(certificate)) => builder.add_root_certificate(certificate),
+            Some(Err(error)) => {
+                warn!("ignoring the CA certificate: {error}");
+                builder
+            }
+        };
+        if options.accept_invalid_certs {
+            warn!("TLS certificates are NOT verified (tls-insecure), anyone in between can read and change the traffic");
+        }
+        let client = builder
+            .danger_accept_invalid_certs(options.accept_invalid_certs)
+            .build()?;
+        Ok(Self {credentials, client, verbose: options.verbose})
+    }
+
+    #[cfg(test)]
+    pub fn credentials(&self) -> &Credentials {
+        &self.credentials
+    }
+
+    /// Creates the RequestBuilder for a method on a URL, with authentication applied.
+    pub fn start_request(&self, method: Method, url: &str) -> RequestBuilder {
+        let request = self.client.request(method, url);
+        match &self.credentials {
+            Credentials::Basic {username, password} => request.basic_auth(username, Some(password)),
+            Credentials::Bearer(token) => request.bearer_auth(token)
+        }
+    }
+
+    /// Sends the request, tracing it and the response in the log: at debug
+    /// level, or at info level if verbose. Credentials are not shown.
+    pub fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        let request = request.build()?;
+        let level = if self.verbose {Level::Info} else {Level::Debug};
+        if log::log_enabled!(level) {
+            Self::_request_trace(&request).iter().for_each(|line| log!(level, "{line}"));
+        }
+        let response = self.client.execute(request)?;
+        if log::log_enabled!(level) {
+            Self::_response_trace(&response).iter().for_each(|line| log!(level, "{line}"));
+        }
+        Ok(response)
+    }
+
+    /// The lines showing a request: method, URL and headers.
+    fn _request_trace(request: &Request) -> Vec<String> {
+        let mut lines = vec!(format!("> {} {}", request.method(), request.url()));
+        for (name, value) in request.headers() {
+            if name == header::AUTHORIZATION || name == header::PROXY_AUTHORIZATION {
+                lines.push(format!("> {name}: [redacted]"));
+            } else {
+                lines.push(format!("> {name}: {}", String::from_utf8_lossy(value.as_bytes())));
+            }
+        }
+        lines
+    }
+
+    /// The lines showing a response: status and the headers telling about the content.
+    fn _response_trace(response: &Response) -> Vec<String> {
+        let mut lines = vec!(format!("< {:?} {}", response.version(), response.status()));
+        for name in &TRACED_RESPONSE_HEADERS {
+            if let Some(value) = response.headers().get(name) {
+                lines.push(format!("< {name}: {}", String::from_utf8_lossy(value.as_bytes())));
+            }
+        }
+        lines
+    }
+
+    /// Determines where a redirect response points to, if it is one.
+    pub fn redirect_target(response: &Response) -> Option<Url> {
+        if !response.status().is_redirection() {
+            return None;
+        }
+        let location = response.headers().get(header::LOCATION)?.to_str().ok()?;
+        response.url().join(location).ok()
+    }
+
+    /// Gets a resource in a representation the Accept header allows,
+    /// following redirects.
+    pub fn get(&self, url: &str, accept: &str) -> Result<Response, Error> {
+        self.get_from(url, accept, 0, None)
+    }
+
+    /// Tells if the credentials given for one URL may be sent to the
+    /// other, i.e. both have the same scheme, host and port.
+    pub fn is_same_origin(url: &Url, other: &Url) -> bool {
+        url.origin() == other.origin()
+    }
+
+    /// Gets a resource like `get`, but only from the given offset on, by
+    /// a Range header. Servers ignoring it answer 200 with the whole content,
+    /// as they do if the resource no longer has the validator given, an
+    /// ETag or a date, sent as If-Range. Redirects to another origin are
+    /// followed without credentials.
+    pub fn get_from(&self, url: &str, accept: &str, offset: u64, validator: Option<&str>) -> Result<Response, Error> {
+        let with_headers = |request: RequestBuilder| {
+            let request = request.header(header::ACCEPT, accept);
+            if offset == 0 {
+                return request;
+            }
+            let request = request.header(header::RANGE, format!("bytes={offset}-"));
+            match validator {
+                Some(validator) => request.header(header::IF_RANGE, validator),
+                None => request
+            }
+        };
+        let mut response = self.send(with_headers(self.start_request(Method::GET, url)))?;
+        let requested = response.url().clone();
+        for _ in 0..MAX_REDIRECTS {
+            match Self::redirect_target(&response) {
+                Some(target) => {
+                    let request = if Self::is_same_origin(&requested, &target) {
+                        self.start_request(Method::GET, target.as_str())
+                    } else {
+                        self.client.request(Method::GET, target.as_str())
+                    };
+                    response = self.send(with_headers(request))?
+                },
+                None => break
+            }
+        }
+        Ok(response)
+    }
+
+    pub fn put<B: Into<Body>>(&self, body: B, url: &str) -> Result<Response, Error> {
+        self.put_if(body, url, &Precondition::None)
+    }
+
+    /// Uploads like `put`, if the precondition holds; otherwise the server
+    /// answers 412 Precondition Failed.
+    pub fn put_if<B: Into<Body>>(&self, body: B, url: &str, precondition: &Precondition) -> Result<Response, Error> {
+        let request = self.start_request(Method::PUT, url)
+            .header(header::CONTENT_TYPE, "application/octet-stream");
+        let request = match precondition {
+            Precondition::None => request,
+            Precondition::IfMatch(etag) => request.header(header::IF_MATCH, etag),
+            Precondition::IfNoneMatch => request.header(header::IF_NONE_MATCH, "*")
+        };
+        self.send(request.body(body))
+    }
+
+    pub fn delete(&self, url: &str) -> Result<Response, Error> {
+        self.send(self.start_request(Method::DELETE, url))
+    }
+
+    /// Copies a resource on the server, the destination given as absolute URL.
+    pub fn copy(&self, from: &str, to: &str, overwrite: bool) -> Result<Response, Error> {
+        self.send(self.start_request(Self::_dav_method("COPY"), from)
+            .header("Destination", to)
+            .header("Overwrite", if overwrite {"T"} else {"F"}))
+    }
+
+    pub fn mkcol(&self, url: &str) -> Result<Response, Error> {
+        self.send(self.start_request(Self::_dav_method("MKCOL"), url))
+    }
+
+    /// Moves a resource, the destination given as absolute URL.
+    pub fn mv(&self, from: &str, to: &str, overwrite: bool) -> Result<Response, Error> {
+        self.send(self.start_request(Self::_dav_method("MOVE"), from)
+            .header("Destination", to)
+            .header("Overwrite", if overwrite {"T"} else {"F"}))
+    }
+
+    /// Lists a collection via PROPFIND with the given Depth header and a
+    /// body built by `propfind_body`. If brief, asks the server to omit
+    /// the properties not found, both in the standard (RFC 8144) and the
+    /// legacy way.
+    pub fn list(&self, url: &str, depth: &str, brief: bool, body: &str) -> Result<Response, Error> {
+        let mut request = self._propfind(url, depth);
+        if brief {
+            request = request.header("Prefer", "return=minimal").header("Brief", "t");
+        }
+        self.send(request.body(body.to_string()))
+    }
+
+    /// Builds a PROPFIND body requesting all properties, or only the given
+    /// DAV: properties. The resourcetype is always requested then, to
+    /// tell collections apart.
+    pub fn propfind_body(properties: Option<&[String]>) -> Result<String, DomError> {
+        let Some(properties) = properties else {
+            return Ok(r#"<?xml version="1.0" encoding="utf-8" ?>
+            <D:propfind xmlns:D="DAV:">
+                <D:allprop/>
+            </D:propfind>
+        "#.to_string());
+        };
+        let mut prop = Element::builder("prop", "DAV:");
+        for name in properties.iter().filter(|name| *name != "resourcetype") {
+            prop = prop.append(Element::bare(name.as_str(), "DAV:"));
+        }
+        let prop = prop.append(Element::bare("resourcetype", "DAV:"));
+        let propfind = Element::builder("propfind", "DAV:").append(prop).build();
+        let mut body = Vec::new();
+        propfind.write_to_decl(&mut body)?;
+        String::from_utf8(body)
+            .map_err(|error| DomError::XmlError(IoError::new(ErrorKind::InvalidData, error).into()))
+    }
+
+    /// Requests a single property of a resource via PROPFIND. The names
+    /// must not need XML escaping.
+    pub fn get_property(&self, url: &str, namespace: &str, name: &str) -> Result<Response, Error> {
+        let body = format!(r#"<?xml version="1.0" encoding="utf-8" ?>
+            <D:propfind xmlns:D="DAV:">
+                <D:prop><P:{name} xmlns:P="{namespace}"/></D:prop>
+            </D:propfind>
+        "#);
+        self.send(self._propfind(url, "0").body(body))
+    }
+
+    fn _propfind(&self, url: &str, depth: &str) -> RequestBuilder {
+        self.start_request(Self::_dav_method("PROPFIND"), url)
+            .header("Depth", depth)
+            .header(header::CONTENT_TYPE, "application/xml; charset=utf-8")
+    }
+
+    fn _dav_method(name: &str) -> Method {
+        // the method names used here are all valid tokens
+        Method::from_bytes(name.as_bytes()).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testserver::{Reply, TestServer};
+
+    #[test]
+    fn test_request_trace_redacts_credentials () {
+        let client = Client::init("user", "secret").unwrap();
+        let request = client.start_request(Method::DELETE, "http://dav.example.com/old.txt").build().unwrap();
+        let trace = Client::_request_trace(&request);
+        assert_eq!(trace[0], "> DELETE http://dav.example.com/old.txt");
+        assert!(trace.contains(&"> authorization: [redacted]".to_string()), "trace is {trace:?}");
+        // "user:secret" in Base64
+        assert!(!trace.iter().any(|line| line.contains("secret") || line.contains("dXNlcjpzZWNyZXQ=")));
+    }
+
+    #[test]
+    fn test_get_redirect_to_other_origin_drops_credentials () {
+        let other = TestServer::start(vec!(Reply::status(200, "OK", &[], "elsewhere")));
+        let location = other.url.join("notes.txt").unwrap();
+        let server = TestServer::start(vec!(
+            Reply::status(302, "Found", &[("Location", "/moved.txt")], ""),
+            Reply::status(302, "Found", &[("Location", location.as_str())], "")
+        ));
+        let client = Client::init("user", "secret").unwrap();
+        let response = client.get(server.url.join("notes.txt").unwrap().as_str(), ACCEPT_ANY).unwrap();
+        assert_eq!(response.text().unwrap(), "elsewhere");
+        let requests = server.finish();
+        assert!(requests.iter().all(|request| request.header("Authorization").is_some()));
+        let requests = other.finish();
+        assert_eq!(requests[0].path, "/notes.txt");
+        assert_eq!(requests[0].header("Authorization"), None);
+    }
+}
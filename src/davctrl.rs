@@ -5,40 +5,78 @@
  * It does not keep a dialgoue or session context with the
  * caller, so is quite stateless apart from configuration.
  * 
- * It is currently build around rustydav, but is in
- * conflict with its design decision to tie one
- * username/password pair to a client instance.
+ * It is built around the thin client in davclient, which
+ * replaced rustydav because that ties one username/password
+ * pair to a client instance and hides the request details.
  * 
  * (c) 2024 Andreas Feldner
  */
-use rustydav::client::Client;
-use rustydav::prelude::{Response, Error as DavError};
+use reqwest::blocking::{Body, Response};
+use reqwest::{header, Certificate, Error as DavError, Method, StatusCode};
 use url::{ParseError as ParseUrlError, Url};
-use std::fs::File;
-use std::path::Path;
-use std::io::{Error as IoError, ErrorKind, BufWriter, Write, BufReader};
+use std::fs::{File, OpenOptions};
+use std::path::{Component, Path, PathBuf};
+use std::io::{Error as IoError, ErrorKind, BufWriter, Read, Write};
 use netrc::Netrc;
 use derive_more::Display;
+use log::{debug, info, warn};
 use minidom::{Element, Error as DomError};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
 use crate::catalogue::CatalogueInfo;
 use crate::filter::FilterCriteria;
+use crate::output::human_size;
+use crate::selection::PathSelection;
+use std::collections::{hash_map::Entry, HashMap, VecDeque};
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use crate::davclient::{Client, ClientOptions, Credentials, Precondition, ProxySetting, ACCEPT_ANY};
+use crate::transfer::{CountingReader, LimitExceeded, LimitedReader, ReplayReader, REPLAY_LIMIT};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 #[derive(Debug, Display)]
 pub enum DavCtrlError {
     Dav(DavError),
     InvalidSource(String),
     InvalidDestination(String),
+    Unauthorized(String),
+    ResponseTooLarge(String),
+    /// A download's size differs from the size the server reports
+    SizeMismatch(String),
+    /// A 405 response, with the methods the server allows instead
+    MethodNotAllowed(String),
+    /// The server didn't answer within the timeout
+    Timeout(String),
+    /// A DELETE answered by multistatus, listing the members not deleted
+    #[display(fmt = "{} member(s) could not be deleted", "_0.len()")]
+    DeleteIncomplete(Vec<FailedMember>),
     Local(IoError)
 }
 impl std::error::Error for DavCtrlError {}
 
 impl From<DavError> for DavCtrlError {
     fn from(e: DavError) -> Self {
-        Self::Dav(e)
+        if e.is_timeout() {
+            let server = e.url().and_then(Url::host_str).unwrap_or("the server");
+            Self::Timeout(format!("{server} did not answer in time, it may be down or unreachable"))
+        } else {
+            Self::Dav(e)
+        }
     }
 }
 impl From<IoError> for DavCtrlError {
     fn from(e: IoError) -> Self {
+        if let Some(exceeded) = e.get_ref().and_then(|inner| inner.downcast_ref::<LimitExceeded>()) {
+            return Self::ResponseTooLarge(exceeded.to_string());
+        }
+        // reading the body of a response fails like this when the server stalls
+        if let Some(dav_error) = e.get_ref().and_then(|inner| inner.downcast_ref::<DavError>()).filter(|dav_error| dav_error.is_timeout()) {
+            let server = dav_error.url().and_then(Url::host_str).unwrap_or("the server");
+            return Self::Timeout(format!("{server} stopped sending in the middle of a response"));
+        }
+        if e.kind() == ErrorKind::TimedOut {
+            return Self::Timeout(format!("the server stopped sending in the middle of a response: {e}"));
+        }
         Self::Local(e)
     }
 }
@@ -51,111 +89,846 @@ impl From<ParseUrlError> for DavCtrlError {
 
 impl From<DomError> for DavCtrlError {
     fn from(e: DomError) -> Self {
-        Self::Local(IoError::new(ErrorKind::Other, e))
+        Self::Local(IoError::other(e))
+    }
+}
+
+/// A resource a bulk operation like a recursive DELETE failed on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedMember {
+    pub url: Url,
+    /// The status line reported, like `HTTP/1.1 423 Locked`, or the error
+    pub status: String
+}
+
+/// The outcome of uploading or downloading one file.
+#[derive(Debug)]
+pub struct TransferResult {
+    pub response: Response,
+    /// Number of bytes uploaded or downloaded
+    pub bytes: u64
+}
+
+/// The target of `get` writing the downloads to stdout instead of files
+pub const STDOUT_TARGET: &str = "-";
+
+/// How often a download is repeated, if its size is wrong
+const VERIFY_SIZE_RETRIES: usize = 2;
+
+/// Characters to encode in a single path segment, i.e. including '/'
+const PATH_SEGMENT: &AsciiSet = &CONTROLS.add(b' ').add(b'"').add(b'#').add(b'<').add(b'>')
+    .add(b'?').add(b'`').add(b'{').add(b'}').add(b'%').add(b'/');
+
+/// What a server tells about itself in its response to OPTIONS.
+#[derive(Debug, Clone, Default)]
+pub struct ServerInfo {
+    /// Content of the Server header, like nginx or SabreDAV
+    pub software: Option<String>,
+    /// DAV compliance classes, like 1, 2 or access-control
+    pub dav_classes: Vec<String>
+}
+
+impl std::fmt::Display for ServerInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let software = self.software.as_deref().unwrap_or("unknown server");
+        if self.dav_classes.is_empty() {
+            write!(f, "{software}, no DAV compliance announced")
+        } else {
+            write!(f, "{software}, DAV compliance classes {}", self.dav_classes.join(", "))
+        }
+    }
+}
+
+/// How operations on several resources deal with a failing item.
+/// All bulk operations (put, get, bulk delete) default to `KeepGoing`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BatchPolicy {
+    /// Abort on the first failing item, leaving the rest untouched
+    FailFast,
+    /// Continue with the remaining items and report all failures
+    #[default]
+    KeepGoing
+}
+
+/// The Depth of a PROPFIND, i.e. how far below the requested resource is listed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Depth {
+    /// Only the resource itself
+    Zero,
+    /// The resource and its direct members
+    #[default]
+    One,
+    /// The whole subtree, which many servers refuse
+    Infinity
+}
+
+impl Depth {
+    /// Parses the values allowed for the Depth header.
+    pub fn parse(depth_desc: &str) -> Option<Depth> {
+        match depth_desc {
+            "0" => Some(Depth::Zero),
+            "1" => Some(Depth::One),
+            "infinity" => Some(Depth::Infinity),
+            _ => None
+        }
+    }
+
+    pub fn as_header(&self) -> &'static str {
+        match self {
+            Depth::Zero => "0",
+            Depth::One => "1",
+            Depth::Infinity => "infinity"
+        }
+    }
+}
+
+/// Limits for the size of responses read by the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseLimits {
+    /// Maximum size of metadata responses, like PROPFIND
+    pub metadata: u64,
+    /// Maximum size of downloads, or None for unlimited
+    pub download: Option<u64>
+}
+
+impl Default for ResponseLimits {
+    fn default() -> Self {
+        // generous for huge collections, but guards against unbounded responses
+        Self {metadata: 64 * 1024 * 1024, download: None}
+    }
+}
+
+/// What happens to the partially written file, when a download fails.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PartialDownloadPolicy {
+    /// Download into a temporary file, removed on failure
+    #[default]
+    Delete,
+    /// Keep the partial file with a `.part` suffix for resuming later
+    Keep
+}
+
+/// The space of a collection as reported by RFC 4331 properties; a
+/// server may report only one of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    pub used_bytes: Option<u64>,
+    pub available_bytes: Option<u64>
+}
+
+impl Quota {
+    pub fn total_bytes(&self) -> Option<u64> {
+        Some(self.used_bytes?.saturating_add(self.available_bytes?))
+    }
+
+}
+
+impl std::fmt::Display for Quota {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let size = |bytes: Option<u64>| bytes.map(human_size).unwrap_or("unknown".to_string());
+        write!(f, "used {}, available {}, total {}", size(self.used_bytes), size(self.available_bytes), size(self.total_bytes()))
+    }
+}
+
+/// Credentials known for a host, or for all other hosts, without the
+/// password.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CredentialEntry {
+    /// None for the default entry
+    pub host: Option<String>,
+    /// The only port the entry applies to, if restricted
+    pub port: Option<u16>,
+    pub login: String,
+    pub has_password: bool
+}
+
+impl std::fmt::Display for CredentialEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.host, self.port) {
+            (None, _) => write!(f, "default")?,
+            (Some(host), None) => write!(f, "{host}")?,
+            (Some(host), Some(port)) => write!(f, "{host}:{port}")?
+        }
+        write!(f, "	login {}	password {}", self.login, if self.has_password {"***"} else {"none"})
+    }
+}
+
+/// An entry found by a recursive walk.
+#[derive(Debug)]
+pub struct WalkEntry {
+    /// Decoded path relative to the root of the walk, ending in `/` for collections
+    pub relative_path: String,
+    pub info: CatalogueInfo
+}
+
+/// The files of a recursive download and how each went.
+#[derive(Debug)]
+pub struct TreeDownload {
+    /// The remote files with the local paths they are downloaded to
+    pub files: Vec<(Url, PathBuf)>,
+    /// Fewer than the files, if the batch was aborted on the first failure
+    pub results: Vec<Result<TransferResult, DavCtrlError>>
+}
+
+/// The files of a recursive copy or move and how each went.
+#[derive(Debug)]
+pub struct TreeCopy {
+    /// The source files with their destinations
+    pub files: Vec<(Url, Url)>,
+    /// Fewer than the files, if the batch was aborted on the first failure;
+    /// the bytes are the sizes the server reported for the sources
+    pub results: Vec<Result<TransferResult, DavCtrlError>>
+}
+
+/// How a collection is listed.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ListOptions {
+    pub depth: Depth,
+    /// Asks the server to omit properties it doesn't have (Prefer: return=minimal)
+    pub brief: bool,
+    /// The DAV: properties to request, like getcontentlength; all if None
+    pub properties: Option<Vec<String>>
+}
+
+impl From<Depth> for ListOptions {
+    fn from(depth: Depth) -> Self {
+        Self {depth, ..Self::default()}
     }
 }
 
 pub struct DavController {
-    netrc: Netrc
+    netrc: Netrc,
+    batch_policy: BatchPolicy,
+    netrc_suffix_match: bool,
+    limits: ResponseLimits,
+    partial_download_policy: PartialDownloadPolicy,
+    verify_size: bool,
+    show_progress: bool,
+    concurrency: usize,
+    client_options: ClientOptions,
+    /// Where the CA certificate in the client options was read from
+    ca_certificate_path: Option<PathBuf>,
+    /// Bearer tokens by host, taking precedence over netrc
+    tokens: HashMap<String, String>,
+    redirect_hint_shown: AtomicBool
 }
 
 impl DavController {
     pub fn new (rc: Netrc) -> Self{
-        Self{netrc: rc}
+        Self{netrc: rc, batch_policy: BatchPolicy::default(), netrc_suffix_match: false,
+            limits: ResponseLimits::default(),
+            partial_download_policy: PartialDownloadPolicy::default(), verify_size: false, show_progress: false, concurrency: 1, client_options: ClientOptions::default(), ca_certificate_path: None, tokens: HashMap::new(), redirect_hint_shown: AtomicBool::new(false)}
     }
-    
-    fn _find_in_netrc(&self, url_host: url::Host<&str>) -> Option<&netrc::Machine> {
-        for host in &self.netrc.hosts {
-            let (netrc_host, netrc_machine) = host;
-            if let url::Host::Domain(hostname) = url_host {
-                if hostname.eq(netrc_host) {
-                    return Some(netrc_machine);
+
+    pub fn set_batch_policy(&mut self, policy: BatchPolicy) {
+        self.batch_policy = policy;
+    }
+
+    pub fn batch_policy(&self) -> BatchPolicy {
+        self.batch_policy
+    }
+
+    pub fn set_response_limits(&mut self, limits: ResponseLimits) {
+        self.limits = limits;
+    }
+
+    pub fn set_partial_download_policy(&mut self, policy: PartialDownloadPolicy) {
+        self.partial_download_policy = policy;
+    }
+
+    /// Compares the size of downloads with what the server reports in
+    /// getcontentlength, repeating mismatching downloads.
+    pub fn set_verify_size(&mut self, verify_size: bool) {
+        self.verify_size = verify_size;
+    }
+
+    /// Shows the progress of downloads on stderr, if it is a terminal.
+    pub fn set_show_progress(&mut self, show_progress: bool) {
+        self.show_progress = show_progress;
+    }
+
+    /// Sets how many items of `put` and `get` are transferred at the same
+    /// time; 1 (the default) transfers one after the other.
+    pub fn set_concurrency(&mut self, concurrency: usize) {
+        self.concurrency = concurrency.max(1);
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    /// Sets how long requests wait for the server, `None` to wait forever.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.client_options.timeout = timeout;
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.client_options.timeout
+    }
+
+    /// Shows each request and response, without credentials.
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.client_options.verbose = verbose;
+    }
+
+    pub fn verbose(&self) -> bool {
+        self.client_options.verbose
+    }
+
+    /// Trusts the root certificate in the PEM file in addition to the
+    /// system's, or only the system's if `None`.
+    pub fn set_ca_certificate(&mut self, path: Option<&Path>) -> Result<(), DavCtrlError> {
+        let pem = match path {
+            None => None,
+            Some(path) => {
+                let pem = std::fs::read(path)?;
+                // fail now rather than with each client built
+                Certificate::from_pem(&pem)?;
+                Some(pem)
+            }
+        };
+        self.client_options.ca_certificate = pem;
+        self.ca_certificate_path = path.map(Path::to_path_buf);
+        Ok(())
+    }
+
+    /// The file of the root certificate trusted in addition to the system's.
+    pub fn ca_certificate(&self) -> Option<&Path> {
+        self.ca_certificate_path.as_deref()
+    }
+
+    /// Accepts any TLS certificate, e.g. for testing a server with a
+    /// self-signed one. A warning is shown with each connection then.
+    pub fn set_accept_invalid_certs(&mut self, accept: bool) {
+        self.client_options.accept_invalid_certs = accept;
+    }
+
+    pub fn accept_invalid_certs(&self) -> bool {
+        self.client_options.accept_invalid_certs
+    }
+
+    pub fn set_proxy(&mut self, proxy: ProxySetting) {
+        self.client_options.proxy = proxy;
+    }
+
+    pub fn proxy(&self) -> &ProxySetting {
+        &self.client_options.proxy
+    }
+
+    /// Lets netrc entries for a parent domain apply to hosts without an
+    /// entry of their own, e.g. `example.com` to `dav.example.com`.
+    pub fn set_netrc_suffix_match(&mut self, suffix_match: bool) {
+        self.netrc_suffix_match = suffix_match;
+    }
+
+    /// Tells if a bulk operation has to stop, given the results so far.
+    pub fn aborts_batch<T, E>(&self, results: &[Result<T, E>]) -> bool {
+        self.batch_policy == BatchPolicy::FailFast && results.last().is_some_and(|result| result.is_err())
+    }
+
+    /// Runs the operation on each item, returning the results in the order
+    /// of the items. Up to `concurrency` items are run at the same time,
+    /// unless the batch has to stop at the first failure, which needs them
+    /// to be run one after the other.
+    fn _run_batch<I: Sync, T: Send>(&self, items: &[I],
+            operation: impl Fn(&I) -> Result<T, DavCtrlError> + Sync) -> Vec<Result<T, DavCtrlError>> {
+        if self.concurrency > 1 && items.len() > 1 && self.batch_policy == BatchPolicy::KeepGoing {
+            match ThreadPoolBuilder::new().num_threads(self.concurrency).build() {
+                Ok(pool) => return pool.install(|| items.par_iter().map(&operation).collect()),
+                Err(error) => warn!("cannot start {} threads, transferring one after the other: {error}", self.concurrency)
+            }
+        }
+        let mut retvec = Vec::new();
+        for item in items {
+            retvec.push(operation(item));
+            if self.aborts_batch(&retvec) {
+                break;
+            }
+        }
+        retvec
+    }
+
+    /// Builds one client for each host and port of the URLs, which the
+    /// items of a batch share instead of connecting each on its own.
+    /// A host the client cannot be built for is left out, for `_client_for`
+    /// to report the error with each of its items.
+    fn _clients_by_host<'a>(&self, urls: impl Iterator<Item = &'a Url>) -> HashMap<String, Client> {
+        let mut clients = HashMap::new();
+        for url in urls {
+            if let Entry::Vacant(entry) = clients.entry(Self::_client_key(url)) {
+                if let Ok(client) = self._build_client(url) {
+                    entry.insert(client);
                 }
             }
         }
-        self.netrc.default.as_ref()
+        clients
+    }
+
+    fn _client_for(&self, clients: &HashMap<String, Client>, url: &Url) -> Result<Client, DavCtrlError> {
+        match clients.get(&Self::_client_key(url)) {
+            Some(client) => Ok(client.clone()),
+            None => self._build_client(url)
+        }
+    }
+
+    /// What the credentials of a client depend on: the host, and the
+    /// port netrc entries may be restricted to.
+    fn _client_key(url: &Url) -> String {
+        format!("{}:{}", url.host_str().unwrap_or_default(), url.port_or_known_default().unwrap_or_default())
     }
     
+    /// Finds the netrc entry for a host and port: one of the host with the
+    /// port wins over one of the host without port, which matches any.
+    fn _find_in_netrc(&self, url_host: url::Host<&str>, port: Option<u16>) -> Option<&netrc::Machine> {
+        // IP addresses can only match exactly
+        let hostname = url_host.to_string();
+        let host_entries = || self.netrc.hosts.iter().filter(|(netrc_host, _)| hostname.eq(netrc_host));
+        let exact_match = host_entries().find(|(_, netrc_machine)| netrc_machine.port.is_some() && netrc_machine.port == port)
+            .or_else(|| host_entries().find(|(_, netrc_machine)| netrc_machine.port.is_none()));
+        if let Some((_, netrc_machine)) = exact_match {
+            return Some(netrc_machine);
+        }
+        if self.netrc_suffix_match && matches!(url_host, url::Host::Domain(_)) {
+            // the most specific parent domain wins
+            let parent_match = self.netrc.hosts.iter()
+                .filter(|(_, netrc_machine)| netrc_machine.port.is_none() || netrc_machine.port == port)
+                .filter(|(netrc_host, _)| hostname.strip_suffix(netrc_host.as_str())
+                    .is_some_and(|prefix| prefix.ends_with('.')))
+                .max_by_key(|(netrc_host, _)| netrc_host.len());
+            if let Some((_, netrc_machine)) = parent_match {
+                return Some(netrc_machine);
+            }
+        }
+        self.netrc.default.as_ref()
+    }
 
-    fn _build_client(&self, url: &Url) -> Client {
+    fn _build_client(&self, url: &Url) -> Result<Client, DavCtrlError> {
+        if let Some(token) = url.host_str().and_then(|host| self.tokens.get(host)) {
+            debug!("authenticating to {url} with a bearer token");
+            return Ok(Client::init_with(Credentials::Bearer(token.clone()), &self.client_options)?);
+        }
         if let Some(hostname) = url.host() {
-            if let Some(machine) = self._find_in_netrc(hostname) {
+            if let Some(machine) = self._find_in_netrc(hostname, url.port_or_known_default()) {
                 if let Some(password) = machine.password.as_ref() {
-                    return Client::init(&machine.login, &password);
+                    debug!("authenticating to {url} as {}", machine.login);
+                    let credentials = Credentials::Basic {username: machine.login.clone(), password: password.clone()};
+                    return Ok(Client::init_with(credentials, &self.client_options)?);
                 }
             }
         }
-        eprintln!("Warning: no username/password found for URL {url}");
-        Client::init("", "")
+        warn!("no username/password found for URL {url}");
+        Ok(Client::init_with(Credentials::Basic {username: String::new(), password: String::new()}, &self.client_options)?)
     }
     
-    fn _put_one (client: &Client, file_path: &Path, target_url: &Url) -> Result<Response, DavCtrlError> {
+    /// The error for an upload the server refused, as its precondition didn't hold.
+    fn _precondition_failed(target_url: &Url, precondition: &Precondition) -> DavCtrlError {
+        DavCtrlError::InvalidDestination(match precondition {
+            Precondition::IfMatch(etag) => format!("{target_url} was changed meanwhile, its ETag is no longer {etag}"),
+            _ => format!("{target_url} already exists")
+        })
+    }
+
+    fn _put_one (client: &Client, file_path: &Path, target_url: &Url, precondition: &Precondition) -> Result<TransferResult, DavCtrlError> {
         if file_path.is_file() {
             let file = File::open(file_path)?;
-            let response = client.put(file, target_url.as_str())?;
-            Self::_ensure_response_ok(response)
+            let bytes = file.metadata()?.len();
+            let response = client.put_if(file, target_url.as_str(), precondition)?;
+            if response.status() == StatusCode::PRECONDITION_FAILED {
+                return Err(Self::_precondition_failed(target_url, precondition));
+            }
+            let response = Self::_ensure_response_ok(response)?;
+            Ok(TransferResult {response, bytes})
         } else {
             Err(DavCtrlError::InvalidSource(format!("Not an existing file: {}", file_path.display())))
         }
     }
     
-    pub fn put (&self, file_paths: &Vec<&Path>, target_base: &Url) -> Vec<Result<Response, DavCtrlError>> {
-        let client = self._build_client(target_base);
-        let mut retvec = Vec::new();
-        for file_path in file_paths {
+    /// Tells if the error indicates that the connection was dropped by the
+    /// peer, e.g. after being idle for too long, so that a retry is promising.
+    fn _is_connection_dropped(error: &DavCtrlError) -> bool {
+        let DavCtrlError::Dav(dav_error) = error else {
+            return false;
+        };
+        let mut source = std::error::Error::source(dav_error);
+        while let Some(cause) = source {
+            if let Some(io_error) = cause.downcast_ref::<IoError>() {
+                if matches!(io_error.kind(), 
+                        ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | 
+                        ErrorKind::BrokenPipe | ErrorKind::UnexpectedEof) {
+                    return true;
+                }
+            }
+            if let Some(hyper_error) = cause.downcast_ref::<hyper::Error>() {
+                if hyper_error.is_incomplete_message() || hyper_error.is_closed() {
+                    return true;
+                }
+            }
+            source = cause.source();
+        }
+        false
+    }
+
+    /// Performs the operation and, if the connection turns out to be dropped,
+    /// rebuilds the client and retries exactly once.
+    fn _with_reconnect<T>(&self, url: &Url, client: &mut Client, 
+            operation: impl Fn(&Client) -> Result<T, DavCtrlError>) -> Result<T, DavCtrlError> {
+        match operation(client) {
+            Err(error) if Self::_is_connection_dropped(&error) => {
+                warn!("connection for {url} was dropped, reconnecting");
+                *client = self._build_client(url)?;
+                operation(client)
+            },
+            result => result
+        }
+    }
+    
+    pub fn put (&self, file_paths: &Vec<&Path>, target_base: &Url) -> Vec<Result<TransferResult, DavCtrlError>> {
+        self.put_if(file_paths, target_base, &Precondition::None)
+    }
+
+    /// Uploads like `put`, each file only if the precondition holds for
+    /// the resource it replaces, to not overwrite the changes of others.
+    pub fn put_if (&self, file_paths: &Vec<&Path>, target_base: &Url, precondition: &Precondition) -> Vec<Result<TransferResult, DavCtrlError>> {
+        let client = match self._build_client(target_base) {
+            Ok(client) => client,
+            // none of the files can be uploaded
+            Err(error) => return vec!(Err(error))
+        };
+        self._run_batch(file_paths, |file_path| {
+            let mut client = client.clone();
             if !target_base.path().ends_with('/') {
                 // non-directory URL is acceptable only for uploading one file
                 if file_paths.len() == 1 {
                     // in this case, do _not_ replace the last path segment with the file's name
-                    retvec.push(Self::_put_one(&client, file_path, target_base));
+                    self._with_reconnect(target_base, &mut client, 
+                        |client| Self::_put_one(client, file_path, target_base, precondition))
                 } else {
-                    retvec.push(Err(DavCtrlError::InvalidDestination(
+                    Err(DavCtrlError::InvalidDestination(
                         format!("Given target URL {target_base} is not a directory and cannot receive multiple files")
-                    )));
+                    ))
                 }
             } else if let Some(filename) = file_path.file_name() {
-                match target_base.join(&filename.to_string_lossy()) {
-                    Ok(target_url) => {
-                        retvec.push(Self::_put_one(&client, file_path, &target_url));
-                    },
-                    Err(error) => {
-                        retvec.push(Err(DavCtrlError::from(error)));
-                    }
-                }
+                // the name is a single path segment, whatever it contains
+                let target_url = Self::member_url(target_base, &filename.to_string_lossy())?;
+                self._with_reconnect(target_base, &mut client,
+                    |client| Self::_put_one(client, file_path, &target_url, precondition))
             } else {
-                retvec.push(
-                    Err(DavCtrlError::InvalidSource(
-                        format!("Source path '{}' does not end with a file name", file_path.display())))
-                );
+                Err(DavCtrlError::InvalidSource(
+                    format!("Source path '{}' does not end with a file name", file_path.display())))
             }
-        }
-        retvec
+        })
     }
     
-    fn _get_one(client: &Client, source: &Url, target_dir: &Path) -> Result<Response, DavCtrlError> {
-        if target_dir.is_dir() {
+    /// Determines the local file to download the source to: into the target
+    /// if it is an existing directory, otherwise the target itself is taken
+    /// as the file name.
+    fn _local_target(source: &Url, target: &Path) -> Result<PathBuf, DavCtrlError> {
+        if target.is_dir() {
             let filename = source.path_segments().
-                    and_then(|paths| paths.last()).
+                    and_then(|mut paths| paths.next_back()).
+                    filter(|filename| !filename.is_empty()).
                     ok_or_else(|| DavCtrlError::InvalidSource(format!("Source URL '{}' contains no filename", source)))?;
-            let file = std::fs::File::create(target_dir.join(filename))?;
-            let mut response = client.get(source.as_str())?;
-            response = Self::_ensure_response_ok(response)?;
-            let mut buffer = BufWriter::new(file);
-            response.copy_to(&mut buffer)?;
-            buffer.flush()?;
-            Ok(response)
+            Ok(target.join(filename))
+        } else if target.to_string_lossy().ends_with(std::path::is_separator) {
+            Err(DavCtrlError::InvalidDestination(format!("Destination '{}' is not a directory", target.display())))
+        } else {
+            Ok(target.to_path_buf())
+        }
+    }
+
+    /// Uploads a stream of unknown length, like stdin, using chunked transfer
+    /// encoding. If the server refuses chunked uploads, the stream is
+    /// buffered to a temporary file and uploaded with known length. This
+    /// is only possible if the server refused before more than
+    /// `REPLAY_LIMIT` bytes were sent, as only these are kept.
+    pub fn put_stream<R: Read + Send + 'static> (&self, source: R, target_url: &Url) -> Result<TransferResult, DavCtrlError> {
+        self.put_stream_if(source, target_url, &Precondition::None)
+    }
+
+    /// Uploads a stream like `put_stream`, if the precondition holds.
+    pub fn put_stream_if<R: Read + Send + 'static> (&self, source: R, target_url: &Url, precondition: &Precondition) -> Result<TransferResult, DavCtrlError> {
+        let client = self._build_client(target_url)?;
+        let (counting_reader, count) = CountingReader::new(source, false);
+        let (reader, replay) = ReplayReader::new(counting_reader, REPLAY_LIMIT);
+        let response = client.put_if(Body::new(reader), target_url.as_str(), precondition)?;
+        let response = if response.status() == StatusCode::LENGTH_REQUIRED || response.status() == StatusCode::NOT_IMPLEMENTED {
+            warn!("server does not accept chunked uploads, buffering the data first");
+            let file = replay.into_complete_file()?.ok_or_else(|| DavCtrlError::InvalidSource(format!(
+                "{target_url} refused the chunked upload after more than {REPLAY_LIMIT} bytes, the stream cannot be sent again")))?;
+            client.put_if(file, target_url.as_str(), precondition)?
         } else {
-            Err(DavCtrlError::InvalidDestination(format!("Destination '{}' is not a directory", target_dir.display())))
+            response
+        };
+        if response.status() == StatusCode::PRECONDITION_FAILED {
+            return Err(Self::_precondition_failed(target_url, precondition));
         }
+        Ok(TransferResult {response: Self::_ensure_response_ok(response)?, bytes: count.load(Ordering::Relaxed)})
     }
     
-    pub fn get (&self, sources: &Vec<&Url>, target_dir: &Path) -> Vec<Result<Response, DavCtrlError>> {
-        let mut retvec = Vec::new();
+    /// Downloads into a temporary file next to the target, which is renamed
+    /// to the target once complete, so the target never is a partial file.
+    /// To resume later, the `Keep` policy downloads into a `.part` file
+    /// instead, which is kept on failure.
+    fn _get_one(&self, client: &Client, source: &Url, target: &Path, accept: &str) -> Result<TransferResult, DavCtrlError> {
+        let final_path = Self::_local_target(source, target)?;
+        match self.partial_download_policy {
+            PartialDownloadPolicy::Delete => {
+                let response = Self::_ensure_response_ok(client.get(source.as_str(), accept)?)?;
+                let directory = match final_path.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => parent,
+                    _ => Path::new(".")
+                };
+                // removed when dropped, unless persisted
+                let mut temp_file = Self::_download_file_in(directory)?;
+                let result = self._download_into(source, response, temp_file.as_file_mut(), 0, self.show_progress)?;
+                temp_file.persist(&final_path).map_err(|error| error.error)?;
+                Ok(result)
+            },
+            PartialDownloadPolicy::Keep => self._get_resuming(client, source, &final_path, accept)
+        }
+    }
+
+    /// Creates the temporary file a download is written to. It gets the
+    /// permissions new files normally get, i.e. as restricted by the umask,
+    /// not the owner-only ones of temporary files.
+    fn _download_file_in(directory: &Path) -> Result<tempfile::NamedTempFile, IoError> {
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(".").suffix(".download");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            builder.permissions(std::fs::Permissions::from_mode(0o666));
+        }
+        builder.tempfile_in(directory)
+    }
+
+    /// Downloads into the `.part` file, continuing a partial download kept
+    /// there before by a Range request, if the server supports it. The
+    /// partial download is only continued if the resource still has the
+    /// ETag or modification date it had then, which is kept next to it.
+    fn _get_resuming(&self, client: &Client, source: &Url, final_path: &Path, accept: &str) -> Result<TransferResult, DavCtrlError> {
+        let part_path = Self::part_path(final_path);
+        let validator_path = Self::_validator_path(&part_path);
+        let validator = std::fs::read_to_string(&validator_path).ok().filter(|validator| !validator.is_empty());
+        // without a validator, the partial content may be of another version
+        let offset = match validator {
+            Some(_) => std::fs::metadata(&part_path).map(|metadata| metadata.len()).unwrap_or(0),
+            None => 0
+        };
+        let response = client.get_from(source.as_str(), accept, offset, validator.as_deref())?;
+        if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            return Err(DavCtrlError::InvalidSource(format!(
+                "{source} cannot be resumed after the {offset} bytes of {}, the server refuses the range; remove it to download again",
+                part_path.display())));
+        }
+        let response = Self::_ensure_response_ok(response)?;
+        let resumed = response.status() == StatusCode::PARTIAL_CONTENT;
+        let (mut part_file, offset) = if resumed {
+            let content_range = response.headers().get(header::CONTENT_RANGE).and_then(|range| range.to_str().ok()).unwrap_or_default();
+            if !content_range.starts_with(&format!("bytes {offset}-")) {
+                return Err(DavCtrlError::InvalidSource(format!(
+                    "{source} answered the request for the bytes from {offset} on with the range '{content_range}'")));
+            }
+            info!("Resuming download of {source} after {offset} bytes");
+            (OpenOptions::new().append(true).open(&part_path)?, offset)
+        } else {
+            // the whole content is sent, as the server ignored the range, the
+            // resource changed, or there's nothing to resume
+            match Self::_validator_of(&response) {
+                Some(validator) => std::fs::write(&validator_path, validator)?,
+                None => Self::_remove_if_exists(&validator_path)?
+            }
+            (File::create(&part_path)?, 0)
+        };
+        match self._download_into(source, response, &mut part_file, offset, self.show_progress) {
+            Ok(transferred) => {
+                std::fs::rename(&part_path, final_path)?;
+                Self::_remove_if_exists(&validator_path)?;
+                Ok(transferred)
+            },
+            Err(error @ DavCtrlError::SizeMismatch(_)) => {
+                // the content is wrong, so it is no base to resume from
+                std::fs::remove_file(&part_path)?;
+                Self::_remove_if_exists(&validator_path)?;
+                Err(error)
+            },
+            Err(error) => {
+                info!("Keeping partial download of {source} as {}", part_path.display());
+                Err(error)
+            }
+        }
+    }
+
+    /// Writes the body of the response to the sink, a file which already
+    /// holds the first `offset` bytes of the resource, or e.g. stdout.
+    fn _download_into(&self, source: &Url, mut response: Response, sink: &mut impl Write, offset: u64, show_progress: bool) -> Result<TransferResult, DavCtrlError> {
+        // dropping the writer on error still writes out what was received
+        let mut buffer = BufWriter::new(sink);
+        let expected_size = response.content_length();
+        // the progress of downloads running in parallel would overwrite each other
+        let in_parallel_batch = rayon::current_thread_index().is_some();
+        let (reader, _) = CountingReader::new(&mut response, show_progress && !in_parallel_batch);
+        let mut reader = reader.expecting(expected_size);
+        let written = offset + match self.limits.download {
+            Some(limit) => std::io::copy(&mut LimitedReader::new(&mut reader, limit), &mut buffer)?,
+            None => std::io::copy(&mut reader, &mut buffer)?
+        };
+        drop(reader);
+        buffer.flush()?;
+        if self.verify_size {
+            let listing = self.ls(source, Depth::Zero, &FilterCriteria::match_all())?;
+            if let Some(size) = listing.first().and_then(|attrs| attrs.size).filter(|size| *size != written) {
+                return Err(DavCtrlError::SizeMismatch(format!("received {written} bytes of {source}, which has {size}")));
+            }
+        }
+        Ok(TransferResult {response, bytes: written})
+    }
+
+    /// Downloads the source into the sink, e.g. stdout, without a progress
+    /// line that would mix with the content. Warns if the content type
+    /// tells that it is no text.
+    pub fn cat (&self, source: &Url, sink: &mut impl Write) -> Result<TransferResult, DavCtrlError> {
+        let mut client = self._build_client(source)?;
+        let response = self._with_reconnect(source, &mut client,
+            |client| Self::_ensure_response_ok(client.get(source.as_str(), ACCEPT_ANY)?))?;
+        let content_type = response.headers().get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+        if let Some(content_type) = content_type.filter(|content_type| !Self::is_text_type(content_type)) {
+            warn!("{source} is {content_type}, which may not display as text");
+        }
+        self._download_into(source, response, sink, 0, false)
+    }
+
+    /// Tells if the content type denotes text, including the structured
+    /// formats like JSON and XML.
+    pub fn is_text_type (content_type: &str) -> bool {
+        let base_type = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        base_type.starts_with("text/") || base_type.ends_with("+xml") || base_type.ends_with("+json")
+            || matches!(base_type.as_str(), "application/json" | "application/xml" | "application/javascript" | "application/x-sh")
+    }
+
+    /// The name of the file keeping the validator of a partial download.
+    fn _validator_path(part_path: &Path) -> PathBuf {
+        let mut validator_name = part_path.file_name().unwrap_or_default().to_os_string();
+        validator_name.push(".validator");
+        part_path.with_file_name(validator_name)
+    }
+
+    /// The value for If-Range telling whether a download can be resumed:
+    /// the ETag, unless it is a weak one, which If-Range doesn't allow, or
+    /// else the modification date.
+    fn _validator_of(response: &Response) -> Option<String> {
+        let value = |name| response.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+        value(header::ETAG).filter(|etag| !etag.starts_with("W/"))
+            .or_else(|| value(header::LAST_MODIFIED))
+    }
+
+    fn _remove_if_exists(path: &Path) -> Result<(), IoError> {
+        match std::fs::remove_file(path) {
+            Err(error) if error.kind() != ErrorKind::NotFound => Err(error),
+            _ => Ok(())
+        }
+    }
+
+    /// The name of the file a download into `path` is written to until complete.
+    pub fn part_path(path: &Path) -> PathBuf {
+        let mut part_name = path.file_name().unwrap_or_default().to_os_string();
+        part_name.push(".part");
+        path.with_file_name(part_name)
+    }
+
+    /// Downloads the sources into the target directory, or into the target
+    /// file if it is not a directory and there is only one source.
+    pub fn get (&self, sources: &Vec<&Url>, target: &Path) -> Vec<Result<TransferResult, DavCtrlError>> {
+        self.get_accepting(sources, target, ACCEPT_ANY)
+    }
+
+    /// Downloads like `get`, asking for the representations the Accept
+    /// header allows, e.g. application/pdf from gateways rendering documents.
+    pub fn get_accepting (&self, sources: &Vec<&Url>, target: &Path, accept: &str) -> Vec<Result<TransferResult, DavCtrlError>> {
+        let clients = self._clients_by_host(sources.iter().copied());
+        if target == Path::new(STDOUT_TARGET) {
+            return self._get_all_into(&clients, sources, accept, &mut std::io::stdout().lock());
+        }
+        self._run_batch(sources, |source| {
+            if sources.len() > 1 && !target.is_dir() {
+                return Err(DavCtrlError::InvalidDestination(
+                    format!("Destination '{}' is not a directory and cannot receive multiple files", target.display())
+                ));
+            }
+            self._get_verified(&clients, source, target, accept)
+        })
+    }
+
+    /// Writes the downloads to the sink, like stdout, one after the other,
+    /// so they don't mix. Sizes are verified, but what is written cannot
+    /// be repeated.
+    fn _get_all_into(&self, clients: &HashMap<String, Client>, sources: &[&Url], accept: &str, sink: &mut impl Write) -> Vec<Result<TransferResult, DavCtrlError>> {
+        let mut results = Vec::new();
         for source in sources {
-            let client = self._build_client(source);
-            retvec.push(Self::_get_one(&client, source, target_dir));
+            results.push(self._client_for(clients, source)
+                .and_then(|client| Ok(client.get(source.as_str(), accept)?))
+                .and_then(Self::_ensure_response_ok)
+                .and_then(|response| self._download_into(source, response, sink, 0, self.show_progress)));
+            if self.aborts_batch(&results) {
+                break;
+            }
         }
-        retvec
+        results
+    }
+
+    /// Downloads one source, repeating it while its size is found not to
+    /// match the size the server reports.
+    fn _get_verified(&self, clients: &HashMap<String, Client>, source: &Url, target: &Path, accept: &str) -> Result<TransferResult, DavCtrlError> {
+        let mut client = self._client_for(clients, source)?;
+        let mut result = self._with_reconnect(source, &mut client, 
+            |client| self._get_one(client, source, target, accept));
+        for _ in 0..VERIFY_SIZE_RETRIES {
+            let Err(DavCtrlError::SizeMismatch(mismatch)) = &result else {
+                break;
+            };
+            warn!("{mismatch}, retrying");
+            result = self._with_reconnect(source, &mut client, 
+                |client| self._get_one(client, source, target, accept));
+        }
+        result
+    }
+
+    /// Downloads a collection recursively into the target directory,
+    /// recreating its collections as directories below it.
+    pub fn get_tree (&self, root: &Url, target: &Path, selection: &PathSelection) -> Result<TreeDownload, DavCtrlError> {
+        if target.exists() && !target.is_dir() {
+            return Err(DavCtrlError::InvalidDestination(format!("Destination '{}' is not a directory", target.display())));
+        }
+        let entries = self.walk(root, None, selection)?;
+        std::fs::create_dir_all(target)?;
+        let mut files = Vec::new();
+        for entry in entries {
+            let local_path = Self::_tree_local_path(target, &entry.relative_path)?;
+            if entry.info.is_collection {
+                std::fs::create_dir_all(&local_path)?;
+            } else {
+                // the collection itself may not be selected, e.g. by --include '*.jpg'
+                if let Some(parent) = local_path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                files.push((entry.info.url, local_path));
+            }
+        }
+        let clients = self._clients_by_host(files.iter().map(|(source, _)| source));
+        let results = self._run_batch(&files, |(source, local_path)| {
+            self._get_verified(&clients, source, local_path, ACCEPT_ANY)
+        });
+        Ok(TreeDownload {files, results})
+    }
+
+    /// The local path of an entry of a walk below the target directory;
+    /// names like `..` sent by the server must not lead out of it.
+    fn _tree_local_path (target: &Path, relative_path: &str) -> Result<PathBuf, DavCtrlError> {
+        let relative = Path::new(relative_path.trim_end_matches('/'));
+        if relative.components().any(|component| !matches!(component, Component::Normal(_))) {
+            return Err(DavCtrlError::InvalidSource(format!("Refusing to download '{relative_path}', which leads out of {}", target.display())));
+        }
+        Ok(target.join(relative))
     }
     
     fn _read_attributes_from_response (&self, base: &Url, response: &Element) -> Result<CatalogueInfo, DavCtrlError> {
@@ -166,65 +939,634 @@ impl DavController {
         Ok(CatalogueInfo::new(base, response))
     }
     
-    pub fn ls (&self, url_to_list: &Url, filter: &FilterCriteria) -> Result<Vec<CatalogueInfo>, DavCtrlError> {
-        let client = self._build_client(url_to_list);
+    pub fn ls (&self, url_to_list: &Url, options: impl Into<ListOptions>, filter: &FilterCriteria) -> Result<Vec<CatalogueInfo>, DavCtrlError> {
+        let options = options.into();
+        if let Some(name) = options.properties.iter().flatten().find(|name| !Self::_is_property_name(name)) {
+            return Err(DavCtrlError::InvalidSource(format!("Invalid property name {name}")));
+        }
+        debug!("listing {url_to_list} with Depth {}", options.depth.as_header());
+        let body = Client::propfind_body(options.properties.as_deref())?;
+        let mut client = self._build_client(url_to_list)?;
         let mut retvec = Vec::new();
-        let mut response = client.list(url_to_list.as_str(), "1")?;
-        response = Self::_ensure_response_ok(response)?;
-        let buf_reader = BufReader::new(response);
-        let root = Element::from_reader(buf_reader)?;
-        if !root.is("multistatus", "DAV:") {
-            return Err(DavCtrlError::Local(IoError::from(ErrorKind::InvalidData)));
-        };
+        let mut response = self._with_reconnect(url_to_list, &mut client, 
+            |client| Ok(client.list(url_to_list.as_str(), options.depth.as_header(), options.brief, &body)?))?;
+        if let Some(redirected_url) = Client::redirect_target(&response) {
+            // typically a collection requested without trailing slash
+            self._hint_redirect(url_to_list, &redirected_url);
+            if !Client::is_same_origin(url_to_list, &redirected_url) {
+                // the credentials for one server are not for another
+                client = self._build_client(&redirected_url)?;
+            }
+            response = self._with_reconnect(&redirected_url, &mut client, 
+                |client| Ok(client.list(redirected_url.as_str(), options.depth.as_header(), options.brief, &body)?))?;
+        }
+        let response = Self::_ensure_response_ok(response)?;
+        let root = self._read_multistatus(response)?;
         
         for content in root.children() {
             if content.is("response", "DAV:") {
-                let attrs = self._read_attributes_from_response(url_to_list, content)?;
-                if filter.matches(&attrs) {
-                    retvec.push(attrs);
-                }
+                retvec.push(self._read_attributes_from_response(url_to_list, content)?);
             }
         }
         
-        Ok(retvec)
+        Ok(filter.filter(retvec))
     }
     
-    pub fn delete (&self, url_to_delete: &Url) -> Result<Response, DavCtrlError> {
-        let client = self._build_client(url_to_delete);
-        let mut response = client.delete(url_to_delete.as_str())?;
-        response = Self::_ensure_response_ok(response)?;
-        Ok(response)
+    /// Lists a collection recursively, descending collection by collection
+    /// with Depth 1, as many servers refuse Depth infinity. `max_depth`
+    /// limits the levels listed, 1 meaning only the members of the root.
+    pub fn walk (&self, root: &Url, max_depth: Option<usize>, selection: &PathSelection) -> Result<Vec<WalkEntry>, DavCtrlError> {
+        self.walk_with_properties(root, max_depth, selection, None)
     }
-    
-    fn _ensure_response_ok(response: Response) -> Result<Response, DavCtrlError> {
-        if ! response.status().is_success() {
-            if let Err(dav_error) = response.error_for_status_ref() {
-                Err(DavCtrlError::from(dav_error))
-            } else {
-                Err(DavCtrlError::from(IoError::new(ErrorKind::Other, "Error status returned without information")))
+
+    /// Walks a collection like `walk`, requesting only the given DAV:
+    /// properties of each member, or all if None.
+    pub fn walk_with_properties (&self, root: &Url, max_depth: Option<usize>, selection: &PathSelection, properties: Option<&[String]>) -> Result<Vec<WalkEntry>, DavCtrlError> {
+        let mut root = root.clone();
+        if !root.path().ends_with('/') {
+            root.set_path(&format!("{}/", root.path()));
+        }
+        let mut entries = Vec::new();
+        let mut pending = VecDeque::from([(root.clone(), 0)]);
+        while let Some((collection, level)) = pending.pop_front() {
+            let options = ListOptions {depth: Depth::One, brief: false, properties: properties.map(<[String]>::to_vec)};
+            for info in self.ls(&collection, options, &FilterCriteria::match_all())? {
+                // the listing includes the collection itself
+                if Self::_is_same_path(&info.url, &collection) {
+                    continue;
+                }
+                let relative_path = Self::_relative_walk_path(&root, &info)?;
+                if info.is_collection {
+                    if !selection.descends_into(&relative_path) {
+                        continue;
+                    }
+                    if max_depth.is_none_or(|max_depth| level + 1 < max_depth) {
+                        let mut member_url = info.url.clone();
+                        if !member_url.path().ends_with('/') {
+                            member_url.set_path(&format!("{}/", member_url.path()));
+                        }
+                        pending.push_back((member_url, level + 1));
+                    }
+                }
+                if selection.selects(&relative_path) {
+                    entries.push(WalkEntry {relative_path, info});
+                }
             }
-        } else {
-            Ok(response)
         }
+        Ok(entries)
     }
-    
-    pub(crate) fn set_default_credentials(&mut self, username: String, password: String) {
-        self.netrc.default = Some(netrc::Machine { 
-            login: username, 
-            password: Some(password), 
-            account: None, 
-            port: None 
-        });
+
+    /// Lists a collection recursively with a single PROPFIND of Depth
+    /// infinity. Servers refusing this with 403 Forbidden, as RFC 4918
+    /// allows, are walked collection by collection instead.
+    pub fn walk_infinity (&self, root: &Url, selection: &PathSelection) -> Result<Vec<WalkEntry>, DavCtrlError> {
+        let mut root = root.clone();
+        if !root.path().ends_with('/') {
+            root.set_path(&format!("{}/", root.path()));
+        }
+        let body = Client::propfind_body(None)?;
+        let mut client = self._build_client(&root)?;
+        let response = self._with_reconnect(&root, &mut client,
+            |client| Ok(client.list(root.as_str(), Depth::Infinity.as_header(), false, &body)?))?;
+        if response.status() == StatusCode::FORBIDDEN {
+            warn!("{root} refuses Depth infinity, listing collection by collection");
+            return self.walk(&root, None, selection);
+        }
+        let multistatus = self._read_multistatus(Self::_ensure_response_ok(response)?)?;
+        let mut entries = Vec::new();
+        for response in multistatus.children().filter(|child| child.is("response", "DAV:")) {
+            let info = self._read_attributes_from_response(&root, response)?;
+            // the listing includes the collection itself
+            if Self::_is_same_path(&info.url, &root) {
+                continue;
+            }
+            let relative_path = Self::_relative_walk_path(&root, &info)?;
+            // members of collections not descended into are left out, like in walk
+            let ancestors_descended = relative_path.trim_end_matches('/').match_indices('/')
+                .all(|(end, _)| selection.descends_into(&relative_path[..=end]));
+            if ancestors_descended && selection.selects(&relative_path) {
+                entries.push(WalkEntry {relative_path, info});
+            }
+        }
+        Ok(entries)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// The decoded path of a listed resource relative to the root of a walk,
+    /// ending in '/' for collections. A resource outside the root is an
+    /// error, so that no part of the tree is left out unnoticed.
+    fn _relative_walk_path (root: &Url, info: &CatalogueInfo) -> Result<String, DavCtrlError> {
+        // servers may encode an href differently than the URL asked for, e.g. %2c for %2C
+        let root_path = Self::_decoded_path(root);
+        let mut relative_path = Self::_decoded_path(&info.url).strip_prefix(root_path.as_str())
+            .filter(|_| info.url.origin() == root.origin())
+            .map(str::to_string)
+            .ok_or_else(|| DavCtrlError::InvalidSource(format!("{} is listed as a member of {root}, but is not within it", info.url)))?;
+        if info.is_collection && !relative_path.ends_with('/') {
+            relative_path.push('/');
+        }
+        Ok(relative_path)
+    }
+
+    /// The decoded path of the URL.
+    fn _decoded_path (url: &Url) -> String {
+        percent_decode_str(url.path()).decode_utf8_lossy().into_owned()
+    }
+
+    /// Tells whether both URLs have the same path, whatever its encoding,
+    /// ignoring a trailing '/'.
+    fn _is_same_path (url: &Url, other: &Url) -> bool {
+        Self::_decoded_path(url).trim_end_matches('/') == Self::_decoded_path(other).trim_end_matches('/')
+    }
+
+    /// Parses a multistatus response, within the limit for metadata.
+    fn _read_multistatus (&self, response: impl Read) -> Result<Element, DavCtrlError> {
+        // read completely before parsing, so an oversized response fails cleanly
+        let mut body = Vec::new();
+        LimitedReader::new(response, self.limits.metadata).read_to_end(&mut body)?;
+        let root = Element::from_reader(body.as_slice())?;
+        if !root.is("multistatus", "DAV:") {
+            return Err(DavCtrlError::Local(IoError::from(ErrorKind::InvalidData)));
+        }
+        Ok(root)
+    }
+
+    /// Tells whether a resource exists, asking for as little as possible.
+    /// A redirect counts as existing, as it typically points from a
+    /// collection's URL without trailing slash to the collection.
+    pub fn exists (&self, url: &Url) -> Result<bool, DavCtrlError> {
+        let body = Client::propfind_body(Some(&[]))?;
+        let mut client = self._build_client(url)?;
+        let response = self._with_reconnect(url, &mut client,
+            |client| Ok(client.list(url.as_str(), Depth::Zero.as_header(), true, &body)?))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            Ok(false)
+        } else if response.status().is_redirection() {
+            Ok(true)
+        } else {
+            Self::_ensure_response_ok(response).map(|_| true)
+        }
+    }
+
+    /// Reads the attributes of a single resource, or None if there is none.
+    pub fn stat (&self, url: &Url) -> Result<Option<CatalogueInfo>, DavCtrlError> {
+        match self.ls(url, Depth::Zero, &FilterCriteria::match_all()) {
+            Err(DavCtrlError::Dav(dav_error)) if dav_error.status() == Some(StatusCode::NOT_FOUND) => Ok(None),
+            result => Ok(result?.into_iter().next())
+        }
+    }
+
+    /// Tells whether a property name is usable in a request as is.
+    fn _is_property_name(name: &str) -> bool {
+        let is_name_char = |character: char| character.is_alphanumeric() || "-_.".contains(character);
+        !name.is_empty() && name.chars().all(is_name_char)
+    }
+
+    /// Reads a single property of a resource, or None if the server
+    /// reports it as not existing.
+    pub fn get_property (&self, url: &Url, namespace: &str, name: &str) -> Result<Option<String>, DavCtrlError> {
+        if !Self::_is_property_name(name) || namespace.contains(['"', '<', '>', '&']) {
+            return Err(DavCtrlError::InvalidSource(format!("Invalid property name {namespace} {name}")));
+        }
+        let mut client = self._build_client(url)?;
+        let response = self._with_reconnect(url, &mut client,
+            |client| Self::_ensure_response_ok(client.get_property(url.as_str(), namespace, name)?))?;
+        let root = self._read_multistatus(response)?;
+        let value = root.children()
+            .filter(|child| child.is("response", "DAV:"))
+            .flat_map(|response| response.children())
+            .filter(|child| child.is("propstat", "DAV:") && CatalogueInfo::is_successful_propstat(child))
+            .filter_map(|propstat| propstat.get_child("prop", "DAV:"))
+            .find_map(|prop| prop.get_child(name, namespace))
+            .map(|property| property.text());
+        Ok(value)
+    }
+
+    /// Moves the source to the destination URL, failing if the destination
+    /// exists.
+    pub fn rename (&self, source: &Url, destination: &Url) -> Result<Response, DavCtrlError> {
+        self.move_to(source, destination, false)
+    }
+
+    /// Moves the source to the destination URL. An existing destination is
+    /// only replaced if `overwrite` is set.
+    pub fn move_to (&self, source: &Url, destination: &Url, overwrite: bool) -> Result<Response, DavCtrlError> {
+        let mut client = self._build_client(source)?;
+        let response = self._with_reconnect(source, &mut client,
+            |client| Ok(client.mv(source.as_str(), destination.as_str(), overwrite)?))?;
+        if response.status() == StatusCode::PRECONDITION_FAILED {
+            // what the server answers to Overwrite: F for an existing destination
+            return Err(DavCtrlError::InvalidDestination(format!("{destination} already exists")));
+        }
+        Self::_ensure_response_ok(response)
+    }
+
+    /// Copies the source to the destination URL on the server, without the
+    /// content passing through the client. An existing destination is only
+    /// replaced if `overwrite` is set.
+    pub fn copy (&self, source: &Url, destination: &Url, overwrite: bool) -> Result<Response, DavCtrlError> {
+        if source.as_str().trim_end_matches('/') == destination.as_str().trim_end_matches('/') {
+            return Err(DavCtrlError::InvalidDestination(format!("{destination} is the source itself")));
+        }
+        let mut client = self._build_client(source)?;
+        let response = self._with_reconnect(source, &mut client,
+            |client| Ok(client.copy(source.as_str(), destination.as_str(), overwrite)?))?;
+        if response.status() == StatusCode::PRECONDITION_FAILED {
+            return Err(DavCtrlError::InvalidDestination(format!("{destination} already exists")));
+        }
+        Self::_ensure_response_ok(response)
+    }
+
+    /// Copies a collection recursively, resource by resource: its collections
+    /// are created at the destination, and each file is copied on its own.
+    /// So without `overwrite`, each file already existing at the destination
+    /// is reported as failed, instead of the whole tree failing.
+    pub fn copy_tree (&self, source: &Url, destination: &Url, overwrite: bool) -> Result<TreeCopy, DavCtrlError> {
+        self._transfer_tree(source, destination, overwrite, false)
+    }
+
+    /// Moves a collection recursively like `copy_tree` copies it. Once all
+    /// files are moved, the collections left at the source are deleted;
+    /// otherwise they are kept with the files not moved.
+    pub fn move_tree (&self, source: &Url, destination: &Url, overwrite: bool) -> Result<TreeCopy, DavCtrlError> {
+        let tree = self._transfer_tree(source, destination, overwrite, true)?;
+        if tree.results.len() == tree.files.len() && tree.results.iter().all(Result::is_ok) {
+            // only empty collections are left, the files are safe at the destination
+            if let Err(error) = self.delete(source) {
+                warn!("{source} is left over after moving its files: {error}");
+            }
+        }
+        Ok(tree)
+    }
+
+    fn _transfer_tree (&self, source: &Url, destination: &Url, overwrite: bool, moving: bool) -> Result<TreeCopy, DavCtrlError> {
+        let source_root = Self::_as_collection(source);
+        let destination_root = Self::_as_collection(destination);
+        if destination_root.as_str().starts_with(source_root.as_str()) {
+            return Err(DavCtrlError::InvalidDestination(format!("{destination} is within {source}")));
+        }
+        let entries = self.walk(&source_root, None, &PathSelection::new())?;
+        self.mkcol(&destination_root)?;
+        let mut files = Vec::new();
+        for entry in entries {
+            let encoded_path: Vec<String> = entry.relative_path.split('/')
+                .map(|segment| utf8_percent_encode(segment, PATH_SEGMENT).to_string())
+                .collect();
+            let mut target = destination_root.clone();
+            target.set_path(&format!("{}{}", destination_root.path(), encoded_path.join("/")));
+            if entry.info.is_collection {
+                self.mkcol(&Self::_as_collection(&target))?;
+            } else {
+                files.push((entry.info.url, target, entry.info.size.unwrap_or(0)));
+            }
+        }
+        let clients = self._clients_by_host(files.iter().map(|(file, _, _)| file));
+        let results = self._run_batch(&files, |(file, target, size)| {
+            let client = self._client_for(&clients, file)?;
+            let response = if moving {
+                client.mv(file.as_str(), target.as_str(), overwrite)?
+            } else {
+                client.copy(file.as_str(), target.as_str(), overwrite)?
+            };
+            if response.status() == StatusCode::PRECONDITION_FAILED {
+                return Err(DavCtrlError::InvalidDestination(format!("{target} already exists")));
+            }
+            Ok(TransferResult {response: Self::_ensure_response_ok(response)?, bytes: *size})
+        });
+        let files = files.into_iter().map(|(file, target, _)| (file, target)).collect();
+        Ok(TreeCopy {files, results})
+    }
+
+    /// The URL of a collection, ending with '/'.
+    fn _as_collection (url: &Url) -> Url {
+        let mut collection = url.clone();
+        if !collection.path().ends_with('/') {
+            collection.set_path(&format!("{}/", collection.path()));
+        }
+        collection
+    }
+
+    /// Copies a resource to another, possibly on a different server, by
+    /// streaming a GET into a PUT, each with the credentials of its host.
+    /// Returns the number of bytes transferred, after verifying the size
+    /// of the destination, if the server reports it. A destination not
+    /// matching the source is deleted again.
+    pub fn transfer (&self, source: &Url, destination: &Url, show_progress: bool) -> Result<u64, DavCtrlError> {
+        let mut source_client = self._build_client(source)?;
+        let response = self._with_reconnect(source, &mut source_client,
+            |client| Self::_ensure_response_ok(client.get(source.as_str(), ACCEPT_ANY)?))?;
+        let expected_size = response.content_length();
+        let (reader, count) = CountingReader::new(response, show_progress);
+        let reader = reader.expecting(expected_size);
+        let body = match expected_size {
+            Some(size) => Body::sized(reader, size),
+            None => Body::new(reader)
+        };
+        let destination_client = self._build_client(destination)?;
+        Self::_ensure_response_ok(destination_client.put(body, destination.as_str())?)?;
+        let transferred = count.load(Ordering::Relaxed);
+        if let Some(size) = expected_size.filter(|size| *size != transferred) {
+            return Err(self._discard_transferred(destination,
+                DavCtrlError::InvalidSource(format!("{source} delivered {transferred} of {size} bytes"))));
+        }
+        let listing = self.ls(destination, Depth::Zero, &FilterCriteria::match_all())?;
+        match listing.first().and_then(|attrs| attrs.size) {
+            Some(size) if size != transferred => Err(self._discard_transferred(destination, DavCtrlError::InvalidDestination(
+                format!("{destination} has {size} bytes after transferring {transferred}")))),
+            Some(_) => Ok(transferred),
+            None => {
+                warn!("{destination} does not report its size, cannot verify the transfer");
+                Ok(transferred)
+            }
+        }
+    }
+
+    /// Deletes the destination of a failed transfer, so no incomplete copy
+    /// is left, returning the error with what became of the destination.
+    fn _discard_transferred (&self, destination: &Url, error: DavCtrlError) -> DavCtrlError {
+        let outcome = match self.delete(destination) {
+            Ok(_) => "it was deleted".to_string(),
+            Err(delete_error) => format!("deleting it failed: {delete_error}")
+        };
+        match error {
+            DavCtrlError::InvalidSource(message) => DavCtrlError::InvalidSource(format!("{message}, {outcome}")),
+            DavCtrlError::InvalidDestination(message) => DavCtrlError::InvalidDestination(format!("{message}, {outcome}")),
+            other => other
+        }
+    }
+
+    /// Determines the URL of the member named `name` of a collection.
+    pub fn member_url (collection: &Url, name: &str) -> Result<Url, DavCtrlError> {
+        if !collection.path().ends_with('/') {
+            return Err(DavCtrlError::InvalidDestination(format!("{collection} is not a collection")));
+        }
+        Ok(collection.join(&utf8_percent_encode(name, PATH_SEGMENT).to_string())?)
+    }
+
+    /// Determines the URL of a sibling named `name` in the same collection as
+    /// `url`, which may be a collection itself.
+    pub fn sibling_url (url: &Url, name: &str) -> Result<Url, DavCtrlError> {
+        let is_collection = url.path().ends_with('/');
+        let mut parent = url.clone();
+        parent.set_path(url.path().trim_end_matches('/'));
+        let encoded_name = utf8_percent_encode(name, PATH_SEGMENT).to_string();
+        let sibling = parent.join(&encoded_name)?;
+        if is_collection {
+            Ok(sibling.join(&format!("{encoded_name}/"))?)
+        } else {
+            Ok(sibling)
+        }
+    }
+
+    /// Creates a collection. Returns false if it already existed, which is
+    /// what a 405 Method Not Allowed to MKCOL means.
+    pub fn mkcol (&self, url: &Url) -> Result<bool, DavCtrlError> {
+        let mut client = self._build_client(url)?;
+        let response = self._with_reconnect(url, &mut client, |client| Ok(client.mkcol(url.as_str())?))?;
+        if response.status() == StatusCode::METHOD_NOT_ALLOWED {
+            Ok(false)
+        } else {
+            Self::_ensure_response_ok(response)?;
+            Ok(true)
+        }
+    }
+
+    /// Ensures all collections on the path from base to target exist. Returns
+    /// each collection's URL and whether it had to be created. A segment
+    /// that exists, but as a file, fails with `InvalidDestination`.
+    pub fn mktree (&self, base: &Url, target: &Url) -> Result<Vec<(Url, bool)>, DavCtrlError> {
+        let base_path = if target.path().starts_with(base.path()) && base.path().ends_with('/') {base.path()} else {"/"};
+        let mut current = target.clone();
+        current.set_path(base_path);
+        let mut outcomes = Vec::new();
+        for segment in target.path()[base_path.len()..].split('/').filter(|segment| !segment.is_empty()) {
+            let collection_path = format!("{}{segment}/", current.path());
+            current.set_path(&collection_path);
+            let created = self.mkcol(&current)?;
+            if !created {
+                // MKCOL is also refused where a file is in the way
+                let mut segment_url = current.clone();
+                segment_url.set_path(collection_path.trim_end_matches('/'));
+                if self.stat(&segment_url)?.is_some_and(|info| !info.is_collection) {
+                    return Err(DavCtrlError::InvalidDestination(format!("{segment_url} exists but is not a collection")));
+                }
+            }
+            outcomes.push((current.clone(), created));
+        }
+        Ok(outcomes)
+    }
+
+    /// Asks the server about itself via OPTIONS.
+    pub fn server_info (&self, url: &Url) -> Result<ServerInfo, DavCtrlError> {
+        let mut client = self._build_client(url)?;
+        let response = self._with_reconnect(url, &mut client, 
+            |client| Self::_ensure_response_ok(client.send(client.start_request(Method::OPTIONS, url.as_str()))?))?;
+        let header_text = |name| response.headers().get_all(name).iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<&str>>()
+            .join(",");
+        let software = Some(header_text(header::SERVER)).filter(|software| !software.is_empty());
+        let dav_classes = header_text(header::HeaderName::from_static("dav")).split(',')
+            .map(|class| class.trim().to_string())
+            .filter(|class| !class.is_empty())
+            .collect();
+        Ok(ServerInfo {software, dav_classes})
+    }
+
+    /// Asks for the RFC 4331 quota of a collection, which is None if the
+    /// server doesn't report it.
+    pub fn quota (&self, url: &Url) -> Result<Option<Quota>, DavCtrlError> {
+        let properties = vec!("quota-used-bytes".to_string(), "quota-available-bytes".to_string());
+        let listing = self.ls(url, ListOptions {depth: Depth::Zero, brief: false, properties: Some(properties)}, &FilterCriteria::match_all())?;
+        Ok(listing.first()
+            .map(|info| Quota {used_bytes: info.quota_used_bytes, available_bytes: info.quota_available_bytes})
+            .filter(|quota| *quota != Quota::default()))
+    }
+
+    /// Sends an arbitrary HTTP method with an optional file as request body.
+    /// The response is returned whatever its status, for the caller to inspect.
+    pub fn raw (&self, method: &str, url: &Url, body_file: Option<&Path>) -> Result<Response, DavCtrlError> {
+        let method = Method::from_bytes(method.as_bytes())
+            .map_err(|_| DavCtrlError::InvalidSource(format!("Not a valid HTTP method: {method}")))?;
+        let client = self._build_client(url)?;
+        let mut request = client.start_request(method, url.as_str());
+        if let Some(path) = body_file {
+            request = request.body(File::open(path)?);
+        }
+        Ok(client.send(request)?)
+    }
+
+    fn _hint_redirect(&self, requested: &Url, redirected: &Url) {
+        if self.redirect_hint_shown.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        if redirected.as_str() == format!("{requested}/") {
+            info!("Hint: {requested} redirects to {redirected}, add a trailing slash to address collections");
+        } else {
+            info!("Hint: {requested} redirects to {redirected}");
+        }
+    }
+    
+    /// Deletes a resource. A collection of which some members could not be
+    /// deleted, e.g. because they are locked, results in `DeleteIncomplete`.
+    pub fn delete (&self, url_to_delete: &Url) -> Result<Response, DavCtrlError> {
+        let mut client = self._build_client(url_to_delete)?;
+        let mut response = self._with_reconnect(url_to_delete, &mut client, 
+            |client| Self::_ensure_response_ok(client.delete(url_to_delete.as_str())?))?;
+        if response.status() == StatusCode::MULTI_STATUS {
+            let root = self._read_multistatus(&mut response)?;
+            let failed_members: Vec<FailedMember> = root.children()
+                .filter(|child| child.is("response", "DAV:"))
+                .filter_map(|member| {
+                    let status = member.get_child("status", "DAV:")?.text();
+                    let succeeded = status.split_whitespace().nth(1)
+                        .is_some_and(|code| code.starts_with('2'));
+                    (!succeeded).then(|| FailedMember {url: CatalogueInfo::new(url_to_delete, member).url, status})
+                })
+                .collect();
+            if !failed_members.is_empty() {
+                return Err(DavCtrlError::DeleteIncomplete(failed_members));
+            }
+        }
+        Ok(response)
+    }
+    
+    /// Deletes a resource like `delete`. If that fails on a collection with
+    /// members, e.g. as the server refuses to delete non-empty collections,
+    /// its members are deleted bottom-up before the collection itself.
+    /// Members that cannot be deleted result in `DeleteIncomplete`. Other
+    /// failures, like missing permissions, are returned as they are.
+    pub fn delete_recursive (&self, url_to_delete: &Url) -> Result<Response, DavCtrlError> {
+        self._delete_recursive(url_to_delete, true)
+    }
+
+    fn _delete_recursive (&self, url_to_delete: &Url, may_be_collection: bool) -> Result<Response, DavCtrlError> {
+        let error = match self.delete(url_to_delete) {
+            Ok(response) => return Ok(response),
+            Err(error) => error
+        };
+        if !may_be_collection || !Self::_is_partial_delete_failure(&error) {
+            return Err(error);
+        }
+        let members: Vec<(Url, bool)> = match self.ls(url_to_delete, Depth::One, &FilterCriteria::match_all()) {
+            // the listing includes the collection itself
+            Ok(listing) => listing.into_iter()
+                .filter(|info| info.url.path().trim_end_matches('/') != url_to_delete.path().trim_end_matches('/'))
+                .map(|info| (info.url, info.is_collection))
+                .collect(),
+            Err(_) => return Err(error)
+        };
+        if members.is_empty() {
+            // no collection, or one failing for other reasons than its members
+            return Err(error);
+        }
+        let mut failed_members = Vec::new();
+        for (member, is_collection) in members {
+            match self._delete_recursive(&member, is_collection) {
+                Ok(_) => (),
+                Err(DavCtrlError::DeleteIncomplete(failed_below)) => failed_members.extend(failed_below),
+                Err(error) => failed_members.push(FailedMember {url: member, status: error.to_string()})
+            }
+            if !failed_members.is_empty() && self.batch_policy == BatchPolicy::FailFast {
+                break;
+            }
+        }
+        if !failed_members.is_empty() {
+            return Err(DavCtrlError::DeleteIncomplete(failed_members));
+        }
+        self.delete(url_to_delete)
+    }
+
+    /// Tells if deleting a collection may have failed because of some of
+    /// its members, so that deleting them one by one may help: some were
+    /// not deleted (207), or the server refuses to delete a collection
+    /// that isn't empty (409) or has locked members (423).
+    fn _is_partial_delete_failure (error: &DavCtrlError) -> bool {
+        match error {
+            DavCtrlError::DeleteIncomplete(_) => true,
+            DavCtrlError::Dav(dav_error) => matches!(dav_error.status(), Some(StatusCode::CONFLICT | StatusCode::LOCKED)),
+            _ => false
+        }
+    }
+
+    fn _ensure_response_ok(response: Response) -> Result<Response, DavCtrlError> {
+        let status = response.status();
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            Err(DavCtrlError::Unauthorized(format!("{status} for {}", response.url())))
+        } else if let Some(redirected_url) = Client::redirect_target(&response) {
+            Err(DavCtrlError::InvalidSource(format!("{} redirects to {redirected_url}", response.url())))
+        } else if status == StatusCode::METHOD_NOT_ALLOWED {
+            let allowed = match response.headers().get(header::ALLOW).and_then(|allow| allow.to_str().ok()) {
+                Some(allow) => format!("the server allows: {allow}"),
+                None => "the server does not tell which methods it allows".to_string()
+            };
+            Err(DavCtrlError::MethodNotAllowed(format!("{status} for {}, {allowed}", response.url())))
+        } else if ! status.is_success() {
+            if let Err(dav_error) = response.error_for_status_ref() {
+                Err(DavCtrlError::from(dav_error))
+            } else {
+                Err(DavCtrlError::from(IoError::other("Error status returned without information")))
+            }
+        } else {
+            Ok(response)
+        }
+    }
+    
+    /// Sets the credentials for one host, taking precedence over netrc.
+    pub fn set_host_credentials(&mut self, host: &str, username: String, password: String) {
+        self.netrc.hosts.retain(|(netrc_host, _)| netrc_host != host);
+        self.netrc.hosts.insert(0, (host.to_string(), netrc::Machine {
+            login: username,
+            password: Some(password),
+            account: None,
+            port: None
+        }));
+    }
+
+    /// Authenticates requests to a host with a bearer token, e.g. an OAuth
+    /// access token, instead of the netrc credentials.
+    pub fn set_host_token(&mut self, host: &str, token: String) {
+        self.tokens.insert(host.to_string(), token);
+    }
+
+    /// The netrc entries and credentials set in the session, in the order
+    /// of the file, the default entry last.
+    pub fn credential_entries(&self) -> Vec<CredentialEntry> {
+        let entry = |host: Option<&String>, machine: &netrc::Machine| CredentialEntry {
+            host: host.cloned(),
+            port: machine.port,
+            login: machine.login.clone(),
+            has_password: machine.password.is_some()
+        };
+        self.netrc.hosts.iter().map(|(host, machine)| entry(Some(host), machine))
+            .chain(self.netrc.default.iter().map(|machine| entry(None, machine)))
+            .collect()
+    }
+
+    /// The hosts authenticated with a bearer token, sorted.
+    pub fn token_hosts(&self) -> Vec<&str> {
+        let mut hosts: Vec<&str> = self.tokens.keys().map(String::as_str).collect();
+        hosts.sort();
+        hosts
+    }
+
+    pub(crate) fn set_default_credentials(&mut self, username: String, password: String) {
+        self.netrc.default = Some(netrc::Machine { 
+            login: username, 
+            password: Some(password), 
+            account: None, 
+            port: None 
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
     use url::Url;
     use netrc::Netrc;
     use crate::filter::FilterCriteria;
     use mktemp::Temp;
+    use crate::testserver::{response, response_with_missing, Reply, TestServer};
     
     const TESTSERVER_URL_STR: &str = "https://www.webdavserver.com/Usere30e1ee/";
         
@@ -237,9 +1579,77 @@ mod tests {
         DavController::new(netrc)
     }
     
+    #[test]
+    fn test_netrc_suffix_match () {
+        let netrc = Netrc::parse(std::io::Cursor::new(
+            "machine example.com login parent password p\n\
+             machine dav.example.com login specific password s\n\
+             machine files.dav.example.com login exact password e\n\
+             default login anonymous password a\n")).unwrap();
+        let mut controller = DavController::new(netrc);
+        let login_for = |controller: &DavController, host: &str|
+            controller._find_in_netrc(url::Host::Domain(host), Some(443)).map(|machine| machine.login.clone());
+        assert_eq!(login_for(&controller, "files.dav.example.com").as_deref(), Some("exact"));
+        assert_eq!(login_for(&controller, "a.dav.example.com").as_deref(), Some("anonymous"));
+        controller.set_netrc_suffix_match(true);
+        assert_eq!(login_for(&controller, "files.dav.example.com").as_deref(), Some("exact"));
+        assert_eq!(login_for(&controller, "a.b.dav.example.com").as_deref(), Some("specific"));
+        assert_eq!(login_for(&controller, "www.example.com").as_deref(), Some("parent"));
+        // only whole labels match
+        assert_eq!(login_for(&controller, "badexample.com").as_deref(), Some("anonymous"));
+    }
+
+    #[test]
+    fn test_netrc_port_match () {
+        let netrc = Netrc::parse(std::io::Cursor::new(
+            "machine dav.example.com login standard password s port 443\n\
+             machine dav.example.com login alternative password a port 8443\n\
+             machine files.example.com login files password f\n\
+             default login anonymous password d\n")).unwrap();
+        let controller = DavController::new(netrc);
+        let username_for = |url: &str| match controller._build_client(&Url::parse(url).unwrap()).unwrap().credentials() {
+            Credentials::Basic {username, ..} => username.clone(),
+            Credentials::Bearer(token) => token.clone()
+        };
+        assert_eq!(username_for("https://dav.example.com/"), "standard");
+        assert_eq!(username_for("https://dav.example.com:8443/"), "alternative");
+        assert_eq!(username_for("http://dav.example.com/"), "anonymous");
+        // entries without port match any
+        assert_eq!(username_for("http://files.example.com:8080/"), "files");
+    }
+
+    #[test]
+    fn test_credential_entries () {
+        let netrc = Netrc::parse(std::io::Cursor::new(
+            "machine dav.example.com login alice password secret port 8443\n\
+             machine files.example.com login bob\n\
+             default login anonymous password guest\n")).unwrap();
+        let mut controller = DavController::new(netrc);
+        controller.set_host_token("api.example.com", "token".to_string());
+        let entries: Vec<String> = controller.credential_entries().iter().map(CredentialEntry::to_string).collect();
+        assert_eq!(entries, vec!(
+            "dav.example.com:8443\tlogin alice\tpassword ***",
+            "files.example.com\tlogin bob\tpassword none",
+            "default\tlogin anonymous\tpassword ***"
+        ));
+        assert!(!entries.iter().any(|entry| entry.contains("secret") || entry.contains("guest")));
+        assert_eq!(controller.token_hosts(), vec!("api.example.com"));
+    }
+
+    #[test]
+    fn test_set_host_credentials () {
+        let netrc = Netrc::parse(std::io::Cursor::new("machine dav.example.com login old password o\n")).unwrap();
+        let mut controller = DavController::new(netrc);
+        controller.set_host_credentials("dav.example.com", "new".to_string(), "n".to_string());
+        controller.set_host_credentials("other.example.com", "other".to_string(), "x".to_string());
+        let machine = controller._find_in_netrc(url::Host::Domain("dav.example.com"), Some(443)).unwrap();
+        assert_eq!((machine.login.as_str(), machine.password.as_deref()), ("new", Some("n")));
+        assert_eq!(controller.netrc.hosts.len(), 2);
+    }
+
     #[test]
     fn test_ls () {
-        let listing_result = get_davcontroller().ls(&get_testserver_url(), &FilterCriteria::match_all());
+        let listing_result = get_davcontroller().ls(&get_testserver_url(), Depth::One, &FilterCriteria::match_all());
         assert!(listing_result.is_ok(), "Error is {}", listing_result.err().unwrap());
         let listing = listing_result.unwrap();
         assert_ne!(listing.len(), 0);
@@ -277,6 +1687,1131 @@ mod tests {
         assert!(found_presentation, "Presentation.key was not downloaded");
     }
     
+    #[test]
+    fn test_get_into_directory () {
+        let server = TestServer::start(vec!(Reply::status(200, "OK", &[], "remote content")));
+        let tempdir = Temp::new_dir().unwrap();
+        let source = server.url.join("dir/remote.txt").unwrap();
+        let get_result = get_davcontroller().get(&vec!(&source), &tempdir);
+        assert!(get_result[0].is_ok(), "Error is {}", get_result[0].as_ref().err().unwrap());
+        assert_eq!(std::fs::read_to_string(tempdir.join("remote.txt")).unwrap(), "remote content");
+    }
+
+    #[test]
+    fn test_get_into_named_file () {
+        let server = TestServer::start(vec!(Reply::status(200, "OK", &[], "remote content")));
+        let tempdir = Temp::new_dir().unwrap();
+        let source = server.url.join("dir/remote.txt").unwrap();
+        let target = tempdir.join("local-name.txt");
+        let get_result = get_davcontroller().get(&vec!(&source), &target);
+        assert!(get_result[0].is_ok(), "Error is {}", get_result[0].as_ref().err().unwrap());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "remote content");
+        assert!(!tempdir.join("remote.txt").exists());
+    }
+
+    #[test]
+    fn test_get_multiple_into_file_rejected () {
+        let tempdir = Temp::new_dir().unwrap();
+        let first = Url::parse("http://127.0.0.1:1/a.txt").unwrap();
+        let second = Url::parse("http://127.0.0.1:1/b.txt").unwrap();
+        let get_result = get_davcontroller().get(&vec!(&first, &second), &tempdir.join("single.txt"));
+        assert!(get_result.iter().all(|result| matches!(result, Err(DavCtrlError::InvalidDestination(_)))));
+    }
+
+    #[test]
+    fn test_ls_follows_trailing_slash_redirect () {
+        let server = TestServer::start(vec!(
+            Reply::status(301, "Moved Permanently", &[("Location", "/dir/")], ""),
+            Reply::listing(&[("/dir/", true), ("/dir/notes.txt", false)])
+        ));
+        let listing = get_davcontroller().ls(&server.url.join("dir").unwrap(), Depth::One, &FilterCriteria::match_all()).unwrap();
+        assert_eq!(listing.len(), 2);
+        assert_eq!(listing[1].url, server.url.join("/dir/notes.txt").unwrap());
+        let requests = server.finish();
+        assert_eq!(requests[1].method, "PROPFIND");
+        assert_eq!(requests[1].path, "/dir/");
+    }
+
+    #[test]
+    fn test_ls_redirect_to_other_origin_uses_its_credentials () {
+        let other = TestServer::start(vec!(Reply::listing(&[("/dir/", true)])));
+        let location = other.url.join("dir/").unwrap();
+        let server = TestServer::start(vec!(
+            Reply::status(301, "Moved Permanently", &[("Location", location.as_str())], "")
+        ));
+        let netrc = Netrc::parse(std::io::Cursor::new(format!("machine 127.0.0.1 port {} login user password secret\n",
+            server.url.port().unwrap()))).unwrap();
+        let listing = DavController::new(netrc).ls(&server.url.join("dir").unwrap(), Depth::One, &FilterCriteria::match_all()).unwrap();
+        assert_eq!(listing.len(), 1);
+        let authorization = server.finish()[0].header("Authorization").map(str::to_string);
+        assert!(authorization.is_some());
+        assert_ne!(other.finish()[0].header("Authorization").map(str::to_string), authorization);
+    }
+
+    #[test]
+    fn test_ls_sends_depth () {
+        let server = TestServer::start(vec!(Reply::listing(&[("/dir/", true)])));
+        let listing = get_davcontroller().ls(&server.url.join("dir/").unwrap(), Depth::Zero, &FilterCriteria::match_all()).unwrap();
+        assert_eq!(listing.len(), 1);
+        assert_eq!(server.finish()[0].header("Depth"), Some("0"));
+        assert_eq!(Depth::parse("infinity"), Some(Depth::Infinity));
+        assert_eq!(Depth::parse("2"), None);
+    }
+
+    #[test]
+    fn test_ls_brief () {
+        // one server honors the preference, the other one lists the missing properties anyway
+        let server = TestServer::start(vec!(
+            Reply::multistatus_with(&[("Preference-Applied", "return=minimal")], &response("/dir/a.txt", "<D:getcontentlength>3</D:getcontentlength>")),
+            Reply::multistatus(&response_with_missing("/dir/a.txt", "<D:getcontentlength>3</D:getcontentlength>", "<D:getcontenttype/>"))
+        ));
+        let options = ListOptions {depth: Depth::One, brief: true, properties: None};
+        let controller = get_davcontroller();
+        for _ in 0..2 {
+            let listing = controller.ls(&server.url.join("dir/").unwrap(), options.clone(), &FilterCriteria::match_all()).unwrap();
+            assert_eq!(listing[0].size, Some(3));
+            assert_eq!(listing[0].file_type, None);
+        }
+        let requests = server.finish();
+        assert_eq!(requests[0].header("Prefer"), Some("return=minimal"));
+        assert_eq!(requests[0].header("Brief"), Some("t"));
+    }
+
+    #[test]
+    fn test_ls_requests_properties () {
+        let server = TestServer::start(vec!(Reply::listing(&[("/dir/", true), ("/dir/a.txt", false)])));
+        let options = ListOptions {properties: Some(vec!("getcontentlength".to_string())), ..ListOptions::default()};
+        let listing = get_davcontroller().ls(&server.url.join("dir/").unwrap(), options, &FilterCriteria::match_all()).unwrap();
+        assert_eq!(listing.len(), 2);
+        assert_eq!(listing[1].size, None);
+        let requests = server.finish();
+        let body: Element = std::str::from_utf8(&requests[0].body).unwrap().parse().unwrap();
+        let prop = body.get_child("prop", "DAV:").unwrap();
+        let names: Vec<&str> = prop.children().map(|property| property.name()).collect();
+        assert_eq!(names, vec!("getcontentlength", "resourcetype"));
+        let invalid = ListOptions {properties: Some(vec!("a b".to_string())), ..ListOptions::default()};
+        assert!(matches!(get_davcontroller().ls(&Url::parse("http://127.0.0.1:1/").unwrap(), invalid, &FilterCriteria::match_all()), Err(DavCtrlError::InvalidSource(_))));
+    }
+
+    #[test]
+    fn test_exists () {
+        let server = TestServer::start(vec!(
+            Reply::status(404, "Not Found", &[], ""),
+            Reply::listing(&[("/a.txt", false)]),
+            Reply::status(403, "Forbidden", &[], "")
+        ));
+        let controller = get_davcontroller();
+        let url = server.url.join("a.txt").unwrap();
+        assert!(!controller.exists(&url).unwrap());
+        assert!(controller.exists(&url).unwrap());
+        assert!(matches!(controller.exists(&url), Err(DavCtrlError::Unauthorized(_))));
+        let requests = server.finish();
+        assert_eq!(requests[0].method, "PROPFIND");
+        assert_eq!(requests[0].header("Depth"), Some("0"));
+    }
+
+    #[test]
+    fn test_walk () {
+        let server = TestServer::start(vec!(
+            Reply::listing(&[("/root/", true), ("/root/a.jpg", false), ("/root/sub%20dir/", true), ("/root/thumbs/", true)]),
+            Reply::listing(&[("/root/sub%20dir/", true), ("/root/sub%20dir/b.jpg", false), ("/root/sub%20dir/c.txt", false)])
+        ));
+        let mut selection = PathSelection::new();
+        selection.exclude("thumbs/").unwrap();
+        let entries = get_davcontroller().walk(&server.url.join("root").unwrap(), None, &selection).unwrap();
+        let paths: Vec<&str> = entries.iter().map(|entry| entry.relative_path.as_str()).collect();
+        assert_eq!(paths, vec!("a.jpg", "sub dir/", "sub dir/b.jpg", "sub dir/c.txt"));
+        let requests = server.finish();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].path, "/root/sub%20dir/");
+    }
+
+    #[test]
+    fn test_walk_infinity () {
+        let server = TestServer::start(vec!(
+            Reply::listing(&[("/root/", true), ("/root/a.jpg", false), ("/root/sub/", true), ("/root/sub/b.jpg", false),
+                ("/root/thumbs/", true), ("/root/thumbs/a.jpg", false)]),
+            Reply::status(403, "Forbidden", &[], "<D:error xmlns:D='DAV:'><D:propfind-finite-depth/></D:error>"),
+            Reply::listing(&[("/root/", true), ("/root/a.jpg", false)])
+        ));
+        let mut selection = PathSelection::new();
+        selection.exclude("thumbs/").unwrap();
+        let controller = get_davcontroller();
+        let entries = controller.walk_infinity(&server.url.join("root").unwrap(), &selection).unwrap();
+        let paths: Vec<&str> = entries.iter().map(|entry| entry.relative_path.as_str()).collect();
+        assert_eq!(paths, vec!("a.jpg", "sub/", "sub/b.jpg"));
+        let entries = controller.walk_infinity(&server.url.join("root/").unwrap(), &selection).unwrap();
+        assert_eq!(entries.len(), 1);
+        let requests = server.finish();
+        assert_eq!(requests[0].header("Depth"), Some("infinity"));
+        assert_eq!(requests[2].header("Depth"), Some("1"));
+    }
+
+    #[test]
+    fn test_walk_max_depth () {
+        let server = TestServer::start(vec!(
+            Reply::listing(&[("/root/", true), ("/root/a.jpg", false), ("/root/sub/", true)])
+        ));
+        let entries = get_davcontroller().walk(&server.url.join("root/").unwrap(), Some(1), &PathSelection::new()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(server.finish().len(), 1);
+    }
+
+    #[test]
+    fn test_get_tree () {
+        let server = TestServer::start(vec!(
+            Reply::listing(&[("/root/", true), ("/root/a.txt", false), ("/root/sub/", true), ("/root/empty/", true)]),
+            Reply::listing(&[("/root/sub/", true), ("/root/sub/b.txt", false)]),
+            Reply::listing(&[("/root/empty/", true)]),
+            Reply::status(200, "OK", &[], "a"),
+            Reply::status(404, "Not Found", &[], "")
+        ));
+        let tempdir = Temp::new_dir().unwrap();
+        let target = tempdir.join("copy");
+        let TreeDownload {files, results} = get_davcontroller().get_tree(&server.url.join("root/").unwrap(), &target, &PathSelection::new()).unwrap();
+        assert_eq!(files, vec!((server.url.join("root/a.txt").unwrap(), target.join("a.txt")),
+            (server.url.join("root/sub/b.txt").unwrap(), target.join("sub").join("b.txt"))));
+        // a failing file doesn't keep the others from being downloaded
+        assert_eq!(results.iter().map(Result::is_ok).collect::<Vec<_>>(), vec!(true, false));
+        assert_eq!(std::fs::read_to_string(target.join("a.txt")).unwrap(), "a");
+        assert!(target.join("empty").is_dir());
+        assert!(target.join("sub").is_dir());
+        assert!(!target.join("sub").join("b.txt").exists());
+        let methods: Vec<String> = server.finish().into_iter().map(|request| request.method).collect();
+        assert_eq!(methods, vec!("PROPFIND", "PROPFIND", "PROPFIND", "GET", "GET"));
+    }
+
+    #[test]
+    fn test_tree_local_path () {
+        let target = Path::new("/tmp/target");
+        assert_eq!(DavController::_tree_local_path(target, "sub dir/a.txt").unwrap(), target.join("sub dir").join("a.txt"));
+        assert_eq!(DavController::_tree_local_path(target, "sub/").unwrap(), target.join("sub"));
+        assert!(DavController::_tree_local_path(target, "../a.txt").is_err());
+        assert!(DavController::_tree_local_path(target, "/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_ls_rejects_oversized_response () {
+        let names: Vec<String> = (0..100).map(|number| format!("/dir/file{number}")).collect();
+        let members: Vec<(&str, bool)> = names.iter().map(|name| (name.as_str(), false)).collect();
+        let server = TestServer::start(vec!(Reply::listing(&members)));
+        let mut controller = get_davcontroller();
+        controller.set_response_limits(ResponseLimits {metadata: 1000, download: None});
+        let listing = controller.ls(&server.url.join("dir/").unwrap(), Depth::One, &FilterCriteria::match_all());
+        assert!(matches!(listing, Err(DavCtrlError::ResponseTooLarge(_))));
+    }
+
+    #[test]
+    fn test_get_respects_download_limit () {
+        let server = TestServer::start(vec!(
+            Reply::status(200, "OK", &[], "0123456789"),
+            Reply::status(200, "OK", &[], "0123456789")
+        ));
+        let tempdir = Temp::new_dir().unwrap();
+        let source = server.url.join("big.bin").unwrap();
+        let mut controller = get_davcontroller();
+        controller.set_response_limits(ResponseLimits {metadata: 1000, download: Some(5)});
+        let get_result = controller.get(&vec!(&source), &tempdir);
+        assert!(matches!(get_result[0], Err(DavCtrlError::ResponseTooLarge(_))));
+        controller.set_response_limits(ResponseLimits {metadata: 1000, download: None});
+        let get_result = controller.get(&vec!(&source), &tempdir);
+        assert!(get_result[0].is_ok());
+    }
+
+    #[test]
+    fn test_timeout () {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/file", listener.local_addr().unwrap())).unwrap();
+        // accepts the connection, but never answers
+        std::thread::spawn(move || {
+            let connection = listener.accept();
+            std::thread::sleep(Duration::from_secs(5));
+            drop(connection);
+        });
+        let mut controller = get_davcontroller();
+        assert_eq!(controller.timeout(), Some(crate::davclient::DEFAULT_TIMEOUT));
+        controller.set_timeout(Some(Duration::from_millis(200)));
+        match controller.exists(&url) {
+            Err(DavCtrlError::Timeout(message)) => assert!(message.contains("127.0.0.1"), "message is {message}"),
+            other => panic!("Unexpected result {other:?}")
+        }
+    }
+
+    #[test]
+    fn test_conditional_put () {
+        let server = TestServer::start(vec!(
+            Reply::status(412, "Precondition Failed", &[], ""),
+            Reply::status(201, "Created", &[], ""),
+            Reply::status(412, "Precondition Failed", &[], "")
+        ));
+        let tempdir = Temp::new_dir().unwrap();
+        let file = tempdir.join("notes.txt");
+        std::fs::write(&file, "mine").unwrap();
+        let controller = get_davcontroller();
+        let etag = Precondition::IfMatch("\"v1\"".to_string());
+        match &controller.put_if(&vec!(file.as_path()), &server.url, &etag)[0] {
+            Err(DavCtrlError::InvalidDestination(message)) => assert!(message.contains("changed meanwhile"), "message is {message}"),
+            other => panic!("Unexpected result {other:?}")
+        }
+        assert!(controller.put_if(&vec!(file.as_path()), &server.url, &Precondition::IfNoneMatch)[0].is_ok());
+        match &controller.put_if(&vec!(file.as_path()), &server.url, &Precondition::IfNoneMatch)[0] {
+            Err(DavCtrlError::InvalidDestination(message)) => assert!(message.contains("already exists"), "message is {message}"),
+            other => panic!("Unexpected result {other:?}")
+        }
+        let requests = server.finish();
+        assert_eq!(requests[0].header("If-Match"), Some("\"v1\""));
+        assert_eq!(requests[1].header("If-None-Match"), Some("*"));
+        assert!(requests[1].header("If-Match").is_none());
+    }
+
+    #[test]
+    fn test_clients_by_host () {
+        let mut controller = get_davcontroller();
+        controller.set_host_credentials("dav.example.com", "alice".to_string(), "first".to_string());
+        controller.set_host_credentials("files.example.org", "bob".to_string(), "second".to_string());
+        let sources: Vec<Url> = ["https://dav.example.com/a.txt", "https://files.example.org/b.txt", "https://dav.example.com/dir/c.txt"]
+            .iter().map(|source| Url::parse(source).unwrap()).collect();
+        let clients = controller._clients_by_host(sources.iter());
+        // one client per host, shared by its sources
+        assert_eq!(clients.len(), 2);
+        let username_for = |source: &Url| match controller._client_for(&clients, source).unwrap().credentials() {
+            Credentials::Basic {username, ..} => username.clone(),
+            Credentials::Bearer(token) => token.clone()
+        };
+        assert_eq!(username_for(&sources[0]), "alice");
+        assert_eq!(username_for(&sources[1]), "bob");
+        assert_eq!(username_for(&sources[2]), "alice");
+        // netrc entries may differ by port
+        let other_port = Url::parse("https://dav.example.com:8443/d.txt").unwrap();
+        assert_eq!(controller._clients_by_host(sources.iter().chain([&other_port])).len(), 3);
+    }
+
+    #[test]
+    fn test_host_credentials () {
+        fn username_for(controller: &DavController, url: &str) -> String {
+            match controller._build_client(&Url::parse(url).unwrap()).unwrap().credentials() {
+                Credentials::Basic {username, ..} => username.clone(),
+                Credentials::Bearer(token) => token.clone()
+            }
+        }
+        let mut controller = get_davcontroller();
+        controller.set_default_credentials("anybody".to_string(), "default".to_string());
+        controller.set_host_credentials("dav.example.com", "alice".to_string(), "first".to_string());
+        controller.set_host_credentials("files.example.org", "bob".to_string(), "second".to_string());
+        assert_eq!(username_for(&controller, "https://dav.example.com/dir/"), "alice");
+        assert_eq!(username_for(&controller, "https://files.example.org/"), "bob");
+        assert_eq!(username_for(&controller, "https://other.example.com/"), "anybody");
+        // logging in again replaces the entry of the host only
+        controller.set_host_credentials("dav.example.com", "carol".to_string(), "third".to_string());
+        assert_eq!(username_for(&controller, "https://dav.example.com/dir/"), "carol");
+        assert_eq!(username_for(&controller, "https://files.example.org/"), "bob");
+    }
+
+    #[test]
+    fn test_bearer_token_takes_precedence () {
+        let server = TestServer::start(vec!(
+            Reply::status(200, "OK", &[], "content"),
+            Reply::status(200, "OK", &[], "content")
+        ));
+        let netrc = Netrc::parse(std::io::Cursor::new("machine 127.0.0.1 login user password secret\n")).unwrap();
+        let mut controller = DavController::new(netrc);
+        let source = server.url.join("file.txt").unwrap();
+        let tempdir = Temp::new_dir().unwrap();
+        assert!(controller.get(&vec!(&source), &tempdir)[0].is_ok());
+        controller.set_host_token("127.0.0.1", "abc.def".to_string());
+        assert!(controller.get(&vec!(&source), &tempdir)[0].is_ok());
+        let requests = server.finish();
+        assert!(requests[0].header("Authorization").is_some_and(|auth| auth.starts_with("Basic ")));
+        assert_eq!(requests[1].header("Authorization"), Some("Bearer abc.def"));
+    }
+
+    #[test]
+    fn test_get_through_proxy () {
+        let proxy = TestServer::start(vec!(Reply::status(200, "OK", &[], "proxied")));
+        let direct = TestServer::start(vec!(Reply::status(200, "OK", &[], "direct")));
+        let tempdir = Temp::new_dir().unwrap();
+        let mut proxy_url = proxy.url.clone();
+        proxy_url.set_username("proxyuser").unwrap();
+        proxy_url.set_password(Some("proxypass")).unwrap();
+        let mut controller = get_davcontroller();
+        controller.set_proxy(ProxySetting::Url(proxy_url));
+        let via_proxy = Url::parse("http://dav.example.invalid/a.txt").unwrap();
+        assert!(controller.get(&vec!(&via_proxy), &tempdir.join("a.txt"))[0].is_ok());
+        assert_eq!(std::fs::read_to_string(tempdir.join("a.txt")).unwrap(), "proxied");
+        controller.set_proxy(ProxySetting::Direct);
+        assert!(controller.get(&vec!(&direct.url.join("b.txt").unwrap()), &tempdir.join("b.txt"))[0].is_ok());
+        assert_eq!(std::fs::read_to_string(tempdir.join("b.txt")).unwrap(), "direct");
+        let proxied = &proxy.finish()[0];
+        assert_eq!(proxied.path, "http://dav.example.invalid/a.txt");
+        // base64 of proxyuser:proxypass
+        assert_eq!(proxied.header("Proxy-Authorization"), Some("Basic cHJveHl1c2VyOnByb3h5cGFzcw=="));
+        assert_eq!(direct.finish()[0].path, "/b.txt");
+    }
+
+    #[test]
+    fn test_parallel_get_keeps_order () {
+        let server = TestServer::start((0..3).map(|_| Reply::status(200, "OK", &[], "content")).collect());
+        let tempdir = Temp::new_dir().unwrap();
+        let unreachable = Url::parse("http://127.0.0.1:1/b.txt").unwrap();
+        let sources = [server.url.join("a.txt").unwrap(), unreachable, server.url.join("c.txt").unwrap(), server.url.join("d.txt").unwrap()];
+        let mut controller = get_davcontroller();
+        controller.set_concurrency(3);
+        let results = controller.get(&sources.iter().collect(), &tempdir);
+        assert_eq!(results.iter().map(Result::is_ok).collect::<Vec<_>>(), vec!(true, false, true, true));
+        for name in ["a.txt", "c.txt", "d.txt"] {
+            assert_eq!(std::fs::read_to_string(tempdir.join(name)).unwrap(), "content");
+        }
+        let mut paths: Vec<String> = server.finish().into_iter().map(|request| request.path).collect();
+        paths.sort();
+        assert_eq!(paths, vec!("/a.txt", "/c.txt", "/d.txt"));
+    }
+
+    #[test]
+    fn test_parallel_put_with_fail_fast () {
+        let server = TestServer::start(vec!(Reply::status(201, "Created", &[], "")));
+        let tempdir = Temp::new_dir().unwrap();
+        std::fs::write(tempdir.join("a.txt"), "a").unwrap();
+        let missing = tempdir.join("missing.txt");
+        let existing = tempdir.join("a.txt");
+        let mut controller = get_davcontroller();
+        controller.set_concurrency(4);
+        controller.set_batch_policy(BatchPolicy::FailFast);
+        // stopping at the first failure means one after the other
+        let results = controller.put(&vec!(missing.as_path(), existing.as_path()), &server.url);
+        assert_eq!(results.len(), 1);
+        controller.set_batch_policy(BatchPolicy::KeepGoing);
+        let results = controller.put(&vec!(missing.as_path(), existing.as_path()), &server.url);
+        assert_eq!(results.iter().map(Result::is_ok).collect::<Vec<_>>(), vec!(false, true));
+        assert_eq!(server.finish()[0].path, "/a.txt");
+    }
+
+    #[test]
+    fn test_get_resumes_partial_download () {
+        let server = TestServer::start(vec!(
+            Reply::status(206, "Partial Content", &[("Content-Range", "bytes 5-9/10")], "56789"),
+            Reply::status(200, "OK", &[("ETag", "\"v2\"")], "0123456789"),
+            Reply::status(416, "Range Not Satisfiable", &[("Content-Range", "bytes */10")], ""),
+            Reply::status(200, "OK", &[("ETag", "W/\"v3\""), ("Last-Modified", "Mon, 01 Jan 2024 10:00:00 GMT")], "0123456789")
+        ));
+        let tempdir = Temp::new_dir().unwrap();
+        let source = server.url.join("big.bin").unwrap();
+        let target = tempdir.join("big.bin");
+        let part = DavController::part_path(&target);
+        let validator = tempdir.join("big.bin.part.validator");
+        let mut controller = get_davcontroller();
+        controller.set_partial_download_policy(PartialDownloadPolicy::Keep);
+        std::fs::write(&part, "01234").unwrap();
+        std::fs::write(&validator, "\"v1\"").unwrap();
+        assert_eq!(controller.get(&vec!(&source), &target)[0].as_ref().unwrap().bytes, 10);
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "0123456789");
+        assert!(!part.exists() && !validator.exists());
+        // a server ignoring the range, or having another version, sends everything again
+        std::fs::write(&part, "012").unwrap();
+        std::fs::write(&validator, "\"v1\"").unwrap();
+        assert!(controller.get(&vec!(&source), &target)[0].is_ok());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "0123456789");
+        std::fs::write(&part, "0123456789xyz").unwrap();
+        std::fs::write(&validator, "\"v2\"").unwrap();
+        match &controller.get(&vec!(&source), &target)[0] {
+            Err(DavCtrlError::InvalidSource(message)) => assert!(message.contains("refuses the range"), "message is {message}"),
+            other => panic!("Unexpected result {other:?}")
+        }
+        assert_eq!(std::fs::read_to_string(&part).unwrap(), "0123456789xyz");
+        // without a validator, the partial download may be of another version
+        std::fs::remove_file(&validator).unwrap();
+        assert!(controller.get(&vec!(&source), &target)[0].is_ok());
+        let requests = server.finish();
+        let ranges: Vec<Option<&str>> = requests.iter().map(|request| request.header("Range")).collect();
+        assert_eq!(ranges, vec!(Some("bytes=5-"), Some("bytes=3-"), Some("bytes=13-"), None));
+        let validators: Vec<Option<&str>> = requests.iter().map(|request| request.header("If-Range")).collect();
+        assert_eq!(validators, vec!(Some("\"v1\""), Some("\"v1\""), Some("\"v2\""), None));
+    }
+
+    #[test]
+    fn test_validator_of_partial_download () {
+        let server = TestServer::start(vec!(
+            Reply::status(200, "OK", &[("ETag", "\"v1\"")], ""),
+            Reply::status(200, "OK", &[("ETag", "W/\"v1\""), ("Last-Modified", "Mon, 01 Jan 2024 10:00:00 GMT")], ""),
+            Reply::status(200, "OK", &[], "")
+        ));
+        let client = Client::init("", "").unwrap();
+        let validators: Vec<Option<String>> = (0..3)
+            .map(|_| DavController::_validator_of(&client.get(server.url.as_str(), ACCEPT_ANY).unwrap()))
+            .collect();
+        assert_eq!(validators, vec!(Some("\"v1\"".to_string()), Some("Mon, 01 Jan 2024 10:00:00 GMT".to_string()), None));
+        server.finish();
+    }
+
+    #[test]
+    fn test_get_partial_download_policy () {
+        let server = TestServer::start(vec!(
+            Reply::status(200, "OK", &[], "0123456789"),
+            Reply::status(200, "OK", &[], "0123456789"),
+            Reply::status(404, "Not Found", &[], "")
+        ));
+        let tempdir = Temp::new_dir().unwrap();
+        let source = server.url.join("big.bin").unwrap();
+        let target = tempdir.join("big.bin");
+        let part = DavController::part_path(&target);
+        assert_eq!(part, tempdir.join("big.bin.part"));
+        let mut controller = get_davcontroller();
+        controller.set_response_limits(ResponseLimits {metadata: 1000, download: Some(5)});
+        assert!(controller.get(&vec!(&source), &target)[0].is_err());
+        assert!(!part.exists() && !target.exists());
+        controller.set_partial_download_policy(PartialDownloadPolicy::Keep);
+        assert!(controller.get(&vec!(&source), &target)[0].is_err());
+        assert_eq!(std::fs::read_to_string(&part).unwrap(), "01234");
+        assert!(!target.exists());
+        // a failed request doesn't leave an empty file behind
+        let missing = tempdir.join("missing.bin");
+        assert!(controller.get(&vec!(&source), &missing)[0].is_err());
+        assert!(!missing.exists() && !DavController::part_path(&missing).exists());
+    }
+
+    #[test]
+    fn test_interrupted_get_leaves_no_file () {
+        let truncated = b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\nConnection: close\r\n\r\n0123456789".to_vec();
+        let server = TestServer::start(vec!(Reply::Raw(truncated.clone()), Reply::Raw(truncated)));
+        let tempdir = Temp::new_dir().unwrap();
+        let get_result = get_davcontroller().get(&vec!(&server.url.join("big.bin").unwrap()), &tempdir);
+        assert!(get_result[0].is_err());
+        assert_eq!(std::fs::read_dir(&tempdir).unwrap().count(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_get_creates_file_with_default_mode () {
+        use std::os::unix::fs::PermissionsExt;
+        let server = TestServer::start(vec!(Reply::status(200, "OK", &[], "content")));
+        let tempdir = Temp::new_dir().unwrap();
+        let target = tempdir.join("notes.txt");
+        let get_result = get_davcontroller().get(&vec!(&server.url.join("notes.txt").unwrap()), &target);
+        assert!(get_result[0].is_ok(), "Error is {}", get_result[0].as_ref().err().unwrap());
+        let created = tempdir.join("created.txt");
+        File::create(&created).unwrap();
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&target), mode(&created));
+    }
+
+    fn size_listing(size: u64) -> Reply {
+        Reply::multistatus(&response("/file.bin", &format!("<D:getcontentlength>{size}</D:getcontentlength>")))
+    }
+
+    #[test]
+    fn test_get_verify_size_retries () {
+        let server = TestServer::start(vec!(
+            Reply::status(200, "OK", &[], "01234"),
+            size_listing(10),
+            Reply::status(200, "OK", &[], "0123456789"),
+            size_listing(10)
+        ));
+        let tempdir = Temp::new_dir().unwrap();
+        let target = tempdir.join("file.bin");
+        let mut controller = get_davcontroller();
+        controller.set_verify_size(true);
+        let get_result = controller.get(&vec!(&server.url.join("file.bin").unwrap()), &target);
+        assert!(get_result[0].is_ok());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "0123456789");
+    }
+
+    #[test]
+    fn test_get_verify_size_fails () {
+        let replies = (0..=VERIFY_SIZE_RETRIES).flat_map(|_| [Reply::status(200, "OK", &[], "01234"), size_listing(10)]).collect();
+        let server = TestServer::start(replies);
+        let tempdir = Temp::new_dir().unwrap();
+        let mut controller = get_davcontroller();
+        controller.set_verify_size(true);
+        let get_result = controller.get(&vec!(&server.url.join("file.bin").unwrap()), &tempdir);
+        assert!(matches!(get_result[0], Err(DavCtrlError::SizeMismatch(_))));
+        assert_eq!(std::fs::read_dir(&tempdir).unwrap().count(), 0);
+        assert_eq!(server.finish().len(), 2 * (VERIFY_SIZE_RETRIES + 1));
+    }
+
+    #[test]
+    fn test_get_follows_redirect () {
+        let server = TestServer::start(vec!(
+            Reply::status(302, "Found", &[("Location", "/moved.txt")], ""),
+            Reply::status(200, "OK", &[], "moved content")
+        ));
+        let tempdir = Temp::new_dir().unwrap();
+        let target = tempdir.join("notes.txt");
+        let get_result = get_davcontroller().get(&vec!(&server.url.join("notes.txt").unwrap()), &target);
+        assert!(get_result[0].is_ok(), "Error is {}", get_result[0].as_ref().err().unwrap());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "moved content");
+    }
+
+    #[test]
+    fn test_get_accepting () {
+        let server = TestServer::start(vec!(
+            Reply::status(200, "OK", &[("Content-Type", "application/pdf")], "%PDF"),
+            Reply::status(200, "OK", &[("Content-Type", "text/plain")], "text")
+        ));
+        let tempdir = Temp::new_dir().unwrap();
+        let source = server.url.join("doc.odt").unwrap();
+        let get_result = get_davcontroller().get_accepting(&vec!(&source), &tempdir, "application/pdf");
+        assert_eq!(get_result[0].as_ref().unwrap().response.headers()["Content-Type"], "application/pdf");
+        assert_eq!(get_result[0].as_ref().unwrap().bytes, 4);
+        assert!(get_davcontroller().get(&vec!(&source), &tempdir)[0].is_ok());
+        let requests = server.finish();
+        assert_eq!(requests[0].header("Accept"), Some("application/pdf"));
+        assert_eq!(requests[1].header("Accept"), Some("*/*"));
+    }
+
+    #[test]
+    fn test_method_not_allowed_tells_allowed_methods () {
+        let server = TestServer::start(vec!(
+            Reply::status(405, "Method Not Allowed", &[("Allow", "GET, PUT, DELETE")], ""),
+            Reply::status(405, "Method Not Allowed", &[], "")
+        ));
+        let source = server.url.join("a.txt").unwrap();
+        let destination = server.url.join("b.txt").unwrap();
+        let controller = get_davcontroller();
+        match controller.rename(&source, &destination) {
+            Err(DavCtrlError::MethodNotAllowed(message)) => assert!(message.ends_with("the server allows: GET, PUT, DELETE"), "message is {message}"),
+            other => panic!("Unexpected result {other:?}")
+        }
+        assert!(matches!(controller.rename(&source, &destination), Err(DavCtrlError::MethodNotAllowed(_))));
+    }
+
+    #[test]
+    fn test_put_counts_bytes () {
+        let server = TestServer::start(vec!(Reply::status(201, "Created", &[], "")));
+        let tempfile = Temp::new_file().unwrap();
+        std::fs::write(&tempfile, "Hello world!\n").unwrap();
+        let put_result = get_davcontroller().put(&vec!(tempfile.as_path()), &server.url.join("hello.txt").unwrap());
+        assert_eq!(put_result[0].as_ref().unwrap().bytes, 13);
+        assert_eq!(server.finish()[0].body.len(), 13);
+    }
+
+    #[test]
+    fn test_put_batch_policy () {
+        let missing_one = PathBuf::from("/nonexistent/one.txt");
+        let missing_two = PathBuf::from("/nonexistent/two.txt");
+        let sources = vec!(missing_one.as_path(), missing_two.as_path());
+        let target = Url::parse("http://127.0.0.1:1/dir/").unwrap();
+        let mut dav_controller = get_davcontroller();
+        assert_eq!(dav_controller.put(&sources, &target).len(), 2);
+        dav_controller.set_batch_policy(BatchPolicy::FailFast);
+        let put_result = dav_controller.put(&sources, &target);
+        assert_eq!(put_result.len(), 1);
+        assert!(put_result[0].is_err());
+    }
+
+    #[test]
+    fn test_quota () {
+        let collection = "<D:resourcetype><D:collection/></D:resourcetype>";
+        let server = TestServer::start(vec!(
+            Reply::multistatus(&response("/dir/", &format!("{collection}<D:quota-used-bytes>1536</D:quota-used-bytes>\
+                <D:quota-available-bytes>3221225472</D:quota-available-bytes>"))),
+            Reply::multistatus(&response_with_missing("/dir/", collection, "<D:quota-used-bytes/><D:quota-available-bytes/>"))
+        ));
+        let controller = get_davcontroller();
+        let quota = controller.quota(&server.url.join("dir/").unwrap()).unwrap().unwrap();
+        assert_eq!(quota, Quota {used_bytes: Some(1536), available_bytes: Some(3221225472)});
+        assert_eq!(quota.to_string(), "used 1.5K, available 3.0G, total 3.0G");
+        assert_eq!(controller.quota(&server.url.join("dir/").unwrap()).unwrap(), None);
+        let requests = server.finish();
+        assert_eq!(requests[0].header("Depth"), Some("0"));
+        assert!(String::from_utf8_lossy(&requests[0].body).contains("quota-available-bytes"));
+    }
+
+    #[test]
+    fn test_set_ca_certificate () {
+        let mut controller = get_davcontroller();
+        let dir = Temp::new_dir().unwrap();
+        assert!(matches!(controller.set_ca_certificate(Some(&dir.join("missing.pem"))), Err(DavCtrlError::Local(_))));
+        let not_a_certificate = dir.join("garbage.pem");
+        std::fs::write(&not_a_certificate, "-----BEGIN CERTIFICATE-----\nnot base64\n-----END CERTIFICATE-----\n").unwrap();
+        assert!(matches!(controller.set_ca_certificate(Some(&not_a_certificate)), Err(DavCtrlError::Dav(_))));
+        assert_eq!(controller.ca_certificate(), None);
+        controller.set_ca_certificate(None).unwrap();
+        assert_eq!(controller.ca_certificate(), None);
+    }
+
+    #[test]
+    fn test_stat () {
+        let server = TestServer::start(vec!(
+            Reply::multistatus(&response("/dir/notes.txt", "<D:getcontentlength>42</D:getcontentlength><D:resourcetype/>")),
+            Reply::status(404, "Not Found", &[], "")
+        ));
+        let controller = get_davcontroller();
+        let attrs = controller.stat(&server.url.join("dir/notes.txt").unwrap()).unwrap().unwrap();
+        assert_eq!(attrs.name, "notes.txt");
+        assert_eq!(attrs.size, Some(42));
+        assert!(controller.stat(&server.url.join("dir/missing.txt").unwrap()).unwrap().is_none());
+        let requests = server.finish();
+        assert_eq!(requests[0].method, "PROPFIND");
+        assert_eq!(requests[0].header("Depth"), Some("0"));
+    }
+
+    #[test]
+    fn test_get_all_into_sink () {
+        let server = TestServer::start(vec!(
+            Reply::status(200, "OK", &[], "first\n"),
+            Reply::status(404, "Not Found", &[], ""),
+            Reply::status(200, "OK", &[], "third\n")
+        ));
+        let controller = get_davcontroller();
+        let sources: Vec<Url> = ["a.txt", "missing.txt", "c.txt"].iter().map(|name| server.url.join(name).unwrap()).collect();
+        let source_refs: Vec<&Url> = sources.iter().collect();
+        let clients = controller._clients_by_host(sources.iter());
+        let mut output = Vec::new();
+        let results = controller._get_all_into(&clients, &source_refs, ACCEPT_ANY, &mut output);
+        assert_eq!(results.len(), 3);
+        assert!(results[1].is_err());
+        assert_eq!(output, b"first\nthird\n");
+        server.finish();
+    }
+
+    #[test]
+    fn test_cat () {
+        let server = TestServer::start(vec!(Reply::status(200, "OK", &[("Content-Type", "text/plain")], "some text\n")));
+        let mut output = Vec::new();
+        let result = get_davcontroller().cat(&server.url.join("notes.txt").unwrap(), &mut output).unwrap();
+        assert_eq!(output, b"some text\n");
+        assert_eq!(result.bytes, 10);
+        let requests = server.finish();
+        assert_eq!(requests[0].method, "GET");
+        assert_eq!(requests[0].path, "/notes.txt");
+    }
+
+    #[test]
+    fn test_is_text_type () {
+        assert!(DavController::is_text_type("text/plain; charset=utf-8"));
+        assert!(DavController::is_text_type("Application/JSON"));
+        assert!(DavController::is_text_type("image/svg+xml"));
+        assert!(!DavController::is_text_type("application/pdf"));
+        assert!(!DavController::is_text_type("image/png"));
+    }
+
+    #[test]
+    fn test_quota_partly_reported () {
+        assert_eq!(Quota {used_bytes: Some(10), available_bytes: None}.to_string(), "used 10, available unknown, total unknown");
+    }
+
+    #[test]
+    fn test_raw_method_with_body () {
+        let server = TestServer::start(vec!(Reply::status(200, "OK", &[], "custom answer")));
+        let body_file = Temp::new_file().unwrap();
+        std::fs::write(&body_file, "<custom/>").unwrap();
+        let response = get_davcontroller().raw("REPORT", &server.url.join("cal/").unwrap(), Some(&body_file)).unwrap();
+        assert_eq!(response.text().unwrap(), "custom answer");
+        let requests = server.finish();
+        assert_eq!(requests[0].method, "REPORT");
+        assert_eq!(requests[0].body, b"<custom/>");
+        assert!(get_davcontroller().raw("NOT A METHOD", &get_testserver_url(), None).is_err());
+    }
+
+    #[test]
+    fn test_server_info () {
+        let server = TestServer::start(vec!(
+            Reply::status(200, "OK", &[("Server", "SabreDAV/4.4"), ("DAV", "1, 2"), ("DAV", "access-control")], "")
+        ));
+        let info = get_davcontroller().server_info(&server.url).unwrap();
+        assert_eq!(info.software.as_deref(), Some("SabreDAV/4.4"));
+        assert_eq!(info.dav_classes, vec!("1", "2", "access-control"));
+        assert_eq!(server.finish()[0].method, "OPTIONS");
+    }
+
+    /// A pipe-like source that does not reveal its length
+    struct Pipe(std::io::Cursor<Vec<u8>>);
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    fn pipe_content() -> (Vec<u8>, Pipe) {
+        let content: Vec<u8> = (0..100_000).map(|number| (number % 251) as u8).collect();
+        (content.clone(), Pipe(std::io::Cursor::new(content)))
+    }
+
+    #[test]
+    fn test_put_stream_chunked () {
+        let server = TestServer::start(vec!(Reply::status(201, "Created", &[], "")));
+        let (content, pipe) = pipe_content();
+        let put_result = get_davcontroller().put_stream(pipe, &server.url.join("piped.bin").unwrap());
+        assert!(put_result.is_ok(), "Error is {}", put_result.err().unwrap());
+        let requests = server.finish();
+        assert_eq!(requests[0].header("transfer-encoding"), Some("chunked"));
+        assert_eq!(requests[0].body, content);
+    }
+
+    #[test]
+    fn test_put_stream_if () {
+        let server = TestServer::start(vec!(
+            Reply::status(201, "Created", &[], ""),
+            Reply::status(412, "Precondition Failed", &[], "")
+        ));
+        let controller = get_davcontroller();
+        let target = server.url.join("out.txt").unwrap();
+        let transferred = controller.put_stream_if(std::io::Cursor::new(b"generated\n".to_vec()), &target, &Precondition::IfNoneMatch).unwrap();
+        assert_eq!(transferred.bytes, 10);
+        match controller.put_stream_if(std::io::Cursor::new(b"again\n".to_vec()), &target, &Precondition::IfNoneMatch) {
+            Err(DavCtrlError::InvalidDestination(message)) => assert!(message.contains("already exists"), "message is {message}"),
+            other => panic!("Unexpected result {other:?}")
+        }
+        let requests = server.finish();
+        assert_eq!(requests[0].body, b"generated\n");
+        assert_eq!(requests[0].header("If-None-Match"), Some("*"));
+    }
+
+    #[test]
+    fn test_put_stream_without_chunked_support () {
+        let server = TestServer::start(vec!(
+            Reply::status(411, "Length Required", &[], ""),
+            Reply::status(201, "Created", &[], "")
+        ));
+        let (content, pipe) = pipe_content();
+        let put_result = get_davcontroller().put_stream(pipe, &server.url.join("piped.bin").unwrap());
+        assert!(put_result.is_ok(), "Error is {}", put_result.err().unwrap());
+        let requests = server.finish();
+        assert_eq!(requests[1].header("content-length"), Some(content.len().to_string().as_str()));
+        assert_eq!(requests[1].body, content);
+    }
+
+    #[test]
+    fn test_put_stream_refused_after_replay_limit () {
+        let server = TestServer::start(vec!(Reply::status(411, "Length Required", &[], "")));
+        let content = std::io::Cursor::new(vec![b'x'; REPLAY_LIMIT + 1]);
+        let put_result = get_davcontroller().put_stream(content, &server.url.join("piped.bin").unwrap());
+        assert!(matches!(put_result, Err(DavCtrlError::InvalidSource(message)) if message.contains("cannot be sent again")));
+        assert_eq!(server.finish().len(), 1);
+    }
+
+    #[test]
+    fn test_mktree () {
+        let server = TestServer::start(vec!(
+            Reply::status(405, "Method Not Allowed", &[], ""),
+            Reply::listing(&[("/dav/a/", true)]),
+            Reply::status(201, "Created", &[], ""),
+            Reply::status(201, "Created", &[], "")
+        ));
+        let base = server.url.join("dav/").unwrap();
+        let outcomes = get_davcontroller().mktree(&base, &base.join("a/b%20c/d").unwrap()).unwrap();
+        let created: Vec<bool> = outcomes.iter().map(|(_, created)| *created).collect();
+        assert_eq!(created, vec!(false, true, true));
+        let requests = server.finish();
+        let requested: Vec<(&str, &str)> = requests.iter().map(|request| (request.method.as_str(), request.path.as_str())).collect();
+        assert_eq!(requested, vec!(("MKCOL", "/dav/a/"), ("PROPFIND", "/dav/a"), ("MKCOL", "/dav/a/b%20c/"), ("MKCOL", "/dav/a/b%20c/d/")));
+    }
+
+    #[test]
+    fn test_mktree_through_file () {
+        let server = TestServer::start(vec!(
+            Reply::status(405, "Method Not Allowed", &[], ""),
+            Reply::listing(&[("/dav/a", false)])
+        ));
+        let base = server.url.join("dav/").unwrap();
+        match get_davcontroller().mktree(&base, &base.join("a/b").unwrap()) {
+            Err(DavCtrlError::InvalidDestination(message)) => assert!(message.ends_with("/dav/a exists but is not a collection"), "message is {message}"),
+            other => panic!("Unexpected result {other:?}")
+        }
+        assert_eq!(server.finish().len(), 2);
+    }
+
+    #[test]
+    fn test_sibling_url () {
+        let file = Url::parse("https://example.com/dir/old.txt").unwrap();
+        assert_eq!(DavController::sibling_url(&file, "new #1.txt").unwrap().as_str(), "https://example.com/dir/new%20%231.txt");
+        let collection = Url::parse("https://example.com/dir/old/").unwrap();
+        assert_eq!(DavController::sibling_url(&collection, "new").unwrap().as_str(), "https://example.com/dir/new/");
+    }
+
+    #[test]
+    fn test_rename () {
+        let server = TestServer::start(vec!(Reply::status(201, "Created", &[], "")));
+        let source = server.url.join("dir/old.txt").unwrap();
+        let destination = server.url.join("dir/new.txt").unwrap();
+        let rename_result = get_davcontroller().rename(&source, &destination);
+        assert!(rename_result.is_ok(), "Error is {}", rename_result.err().unwrap());
+        let requests = server.finish();
+        assert_eq!(requests[0].method, "MOVE");
+        assert_eq!(requests[0].path, "/dir/old.txt");
+        assert_eq!(requests[0].header("destination"), Some(destination.as_str()));
+        assert_eq!(requests[0].header("overwrite"), Some("F"));
+    }
+
+    #[test]
+    fn test_copy () {
+        let server = TestServer::start(vec!(
+            Reply::status(201, "Created", &[], ""),
+            Reply::status(412, "Precondition Failed", &[], "")
+        ));
+        let source = server.url.join("dir/a.txt").unwrap();
+        let destination = server.url.join("dir/b.txt").unwrap();
+        let controller = get_davcontroller();
+        assert!(controller.copy(&source, &destination, true).is_ok());
+        assert!(matches!(controller.copy(&source, &destination, false), Err(DavCtrlError::InvalidDestination(_))));
+        assert!(matches!(controller.copy(&source, &source, true), Err(DavCtrlError::InvalidDestination(_))));
+        let requests = server.finish();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].method, "COPY");
+        assert_eq!(requests[0].header("Destination"), Some(destination.as_str()));
+        assert_eq!(requests[0].header("Overwrite"), Some("T"));
+        assert_eq!(requests[1].header("Overwrite"), Some("F"));
+    }
+
+    #[test]
+    fn test_copy_tree_onto_partly_existing_destination () {
+        let server = TestServer::start(vec!(
+            Reply::listing(&[("/src/", true), ("/src/a.txt", false), ("/src/sub/", true)]),
+            Reply::listing(&[("/src/sub/", true), ("/src/sub/b.txt", false)]),
+            Reply::status(405, "Method Not Allowed", &[], ""),
+            Reply::status(201, "Created", &[], ""),
+            Reply::status(412, "Precondition Failed", &[], ""),
+            Reply::status(201, "Created", &[], "")
+        ));
+        let source = server.url.join("src/").unwrap();
+        let destination = server.url.join("dst/").unwrap();
+        let tree = get_davcontroller().copy_tree(&source, &destination, false).unwrap();
+        assert_eq!(tree.files, vec!(
+            (server.url.join("src/a.txt").unwrap(), server.url.join("dst/a.txt").unwrap()),
+            (server.url.join("src/sub/b.txt").unwrap(), server.url.join("dst/sub/b.txt").unwrap())
+        ));
+        match &tree.results[0] {
+            Err(DavCtrlError::InvalidDestination(message)) => assert!(message.ends_with("/dst/a.txt already exists"), "message is {message}"),
+            other => panic!("Unexpected result {other:?}")
+        }
+        assert!(tree.results[1].is_ok());
+        let expected_destination = server.url.join("dst/sub/b.txt").unwrap();
+        let requests = server.finish();
+        let methods: Vec<&str> = requests.iter().map(|request| request.method.as_str()).collect();
+        assert_eq!(methods, vec!("PROPFIND", "PROPFIND", "MKCOL", "MKCOL", "COPY", "COPY"));
+        assert_eq!(requests[3].path, "/dst/sub/");
+        assert_eq!(requests[5].path, "/src/sub/b.txt");
+        assert_eq!(requests[5].header("Destination"), Some(expected_destination.as_str()));
+        assert!(requests[4..].iter().all(|request| request.header("Overwrite") == Some("F")));
+    }
+
+    #[test]
+    fn test_move_tree () {
+        let server = TestServer::start(vec!(
+            Reply::listing(&[("/src/", true), ("/src/a.txt", false)]),
+            Reply::status(201, "Created", &[], ""),
+            Reply::status(204, "No Content", &[], ""),
+            Reply::status(204, "No Content", &[], "")
+        ));
+        let source = server.url.join("src/").unwrap();
+        let tree = get_davcontroller().move_tree(&source, &server.url.join("dst/").unwrap(), true).unwrap();
+        assert!(tree.results[0].is_ok());
+        let requests = server.finish();
+        assert_eq!(requests[2].method, "MOVE");
+        assert_eq!(requests[2].header("Overwrite"), Some("T"));
+        assert_eq!(requests[3].method, "DELETE");
+        assert_eq!(requests[3].path, "/src/");
+        assert!(matches!(get_davcontroller().copy_tree(&source, &source.join("sub/").unwrap(), false),
+            Err(DavCtrlError::InvalidDestination(_))));
+    }
+
+    #[test]
+    fn test_move_tree_with_differently_encoded_href () {
+        let server = TestServer::start(vec!(
+            Reply::listing(&[("/my%2Cdir/", true), ("/my%2cdir/a%2cb.txt", false)]),
+            Reply::status(201, "Created", &[], ""),
+            Reply::status(201, "Created", &[], ""),
+            Reply::status(204, "No Content", &[], "")
+        ));
+        let source = server.url.join("my%2Cdir/").unwrap();
+        let tree = get_davcontroller().move_tree(&source, &server.url.join("dst/").unwrap(), false).unwrap();
+        assert_eq!(tree.files, vec!((server.url.join("my%2cdir/a%2cb.txt").unwrap(), server.url.join("dst/a,b.txt").unwrap())));
+        assert!(tree.results[0].is_ok());
+        let requests = server.finish();
+        let methods: Vec<&str> = requests.iter().map(|request| request.method.as_str()).collect();
+        assert_eq!(methods, vec!("PROPFIND", "MKCOL", "MOVE", "DELETE"));
+        assert_eq!(requests[3].path, "/my%2Cdir/");
+    }
+
+    #[test]
+    fn test_move_tree_refuses_member_outside_source () {
+        let server = TestServer::start(vec!(
+            Reply::listing(&[("/src/", true), ("/src/a.txt", false), ("/elsewhere/b.txt", false)])
+        ));
+        let source = server.url.join("src/").unwrap();
+        let result = get_davcontroller().move_tree(&source, &server.url.join("dst/").unwrap(), false);
+        assert!(matches!(result, Err(DavCtrlError::InvalidSource(_))), "result is {result:?}");
+        // nothing is moved, let alone deleted
+        let requests = server.finish();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "PROPFIND");
+    }
+
+    #[test]
+    fn test_rename_keeps_existing_destination () {
+        let server = TestServer::start(vec!(Reply::status(412, "Precondition Failed", &[], "")));
+        let source = server.url.join("old.txt").unwrap();
+        let destination = server.url.join("new.txt").unwrap();
+        assert!(matches!(get_davcontroller().rename(&source, &destination), Err(DavCtrlError::InvalidDestination(_))));
+    }
+
+    fn transfer_replies(reported_size: usize) -> Vec<Reply> {
+        vec!(
+            Reply::status(200, "OK", &[], "some content"),
+            Reply::status(201, "Created", &[], ""),
+            Reply::multistatus(&response("/target/copy.txt", &format!("<D:getcontentlength>{reported_size}</D:getcontentlength>")))
+        )
+    }
+
+    #[test]
+    fn test_transfer () {
+        let server = TestServer::start(transfer_replies(12));
+        let transferred = get_davcontroller().transfer(
+            &server.url.join("source/orig.txt").unwrap(), &server.url.join("target/copy.txt").unwrap(), false);
+        assert_eq!(transferred.unwrap(), 12);
+        let requests = server.finish();
+        let methods: Vec<&str> = requests.iter().map(|request| request.method.as_str()).collect();
+        assert_eq!(methods, vec!("GET", "PUT", "PROPFIND"));
+        assert_eq!(requests[1].path, "/target/copy.txt");
+        assert_eq!(requests[1].body, b"some content");
+    }
+
+    #[test]
+    fn test_transfer_detects_size_mismatch () {
+        let mut replies = transfer_replies(4);
+        replies.push(Reply::status(204, "No Content", &[], ""));
+        let server = TestServer::start(replies);
+        let transferred = get_davcontroller().transfer(
+            &server.url.join("source/orig.txt").unwrap(), &server.url.join("target/copy.txt").unwrap(), false);
+        match transferred {
+            Err(DavCtrlError::InvalidDestination(message)) => assert!(message.ends_with("it was deleted"), "message is {message}"),
+            other => panic!("Unexpected result {other:?}")
+        }
+        let requests = server.finish();
+        assert_eq!((requests[3].method.as_str(), requests[3].path.as_str()), ("DELETE", "/target/copy.txt"));
+    }
+
+    #[test]
+    fn test_get_property () {
+        let server = TestServer::start(vec!(
+            Reply::multistatus(&response("/a.txt", r#"<X:reviewer xmlns:X="urn:example">Alice</X:reviewer>"#)),
+            Reply::multistatus(&response_with_missing("/a.txt", "", r#"<reviewer xmlns="urn:example"/>"#))
+        ));
+        let controller = get_davcontroller();
+        let url = server.url.join("a.txt").unwrap();
+        assert_eq!(controller.get_property(&url, "urn:example", "reviewer").unwrap().as_deref(), Some("Alice"));
+        assert_eq!(controller.get_property(&url, "urn:example", "reviewer").unwrap(), None);
+        assert!(matches!(controller.get_property(&url, "urn:example", "<x/>"), Err(DavCtrlError::InvalidSource(_))));
+        let requests = server.finish();
+        assert_eq!(requests[0].header("Depth"), Some("0"));
+        assert!(String::from_utf8_lossy(&requests[0].body).contains(r#"<P:reviewer xmlns:P="urn:example"/>"#));
+    }
+
+    #[test]
+    fn test_delete_reports_failed_members () {
+        let server = TestServer::start(vec!(
+            Reply::multistatus("<D:response><D:href>/dir/locked.txt</D:href><D:status>HTTP/1.1 423 Locked</D:status></D:response>\
+                <D:response><D:href>/dir/sub/</D:href><D:status>HTTP/1.1 424 Failed Dependency</D:status></D:response>"),
+            Reply::multistatus("")
+        ));
+        let controller = get_davcontroller();
+        let delete_result = controller.delete(&server.url.join("dir/").unwrap());
+        let Err(DavCtrlError::DeleteIncomplete(failed_members)) = delete_result else {
+            panic!("Expected DeleteIncomplete, got {delete_result:?}");
+        };
+        assert_eq!(failed_members, vec!(
+            FailedMember {url: server.url.join("/dir/locked.txt").unwrap(), status: "HTTP/1.1 423 Locked".to_string()},
+            FailedMember {url: server.url.join("/dir/sub/").unwrap(), status: "HTTP/1.1 424 Failed Dependency".to_string()}
+        ));
+        // an empty multistatus means nothing failed
+        assert!(controller.delete(&server.url.join("other/").unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_delete_recursive () {
+        let server = TestServer::start(vec!(
+            Reply::status(409, "Conflict", &[], ""),
+            Reply::listing(&[("/dir/", true), ("/dir/a.txt", false), ("/dir/sub/", true), ("/dir/c.txt", false)]),
+            Reply::status(204, "No Content", &[], ""),
+            Reply::status(409, "Conflict", &[], ""),
+            Reply::listing(&[("/dir/sub/", true), ("/dir/sub/b.txt", false)]),
+            Reply::status(423, "Locked", &[], ""),
+            Reply::status(204, "No Content", &[], "")
+        ));
+        let result = get_davcontroller().delete_recursive(&server.url.join("dir/").unwrap());
+        let Err(DavCtrlError::DeleteIncomplete(failed_members)) = result else {
+            panic!("Expected DeleteIncomplete, got {result:?}");
+        };
+        // the descendant failing is reported, its siblings are deleted anyway
+        assert_eq!(failed_members.len(), 1);
+        assert_eq!(failed_members[0].url, server.url.join("/dir/sub/b.txt").unwrap());
+        assert!(failed_members[0].status.contains("423"), "status is {}", failed_members[0].status);
+        let requests: Vec<(String, String)> = server.finish().into_iter().map(|request| (request.method, request.path)).collect();
+        assert_eq!(requests.last(), Some(&("DELETE".to_string(), "/dir/c.txt".to_string())));
+        // the locked file isn't listed, as descending can't help there
+        assert_eq!(requests.iter().filter(|(method, _)| method == "PROPFIND").count(), 2);
+        assert_eq!(requests.len(), 7);
+    }
+
+    #[test]
+    fn test_delete_recursive_keeps_other_errors () {
+        let server = TestServer::start(vec!(
+            Reply::status(403, "Forbidden", &[], ""),
+            Reply::status(404, "Not Found", &[], "")
+        ));
+        let controller = get_davcontroller();
+        assert!(matches!(controller.delete_recursive(&server.url.join("dir/").unwrap()), Err(DavCtrlError::Unauthorized(_))));
+        match controller.delete_recursive(&server.url.join("gone/").unwrap()) {
+            Err(DavCtrlError::Dav(dav_error)) => assert_eq!(dav_error.status(), Some(StatusCode::NOT_FOUND)),
+            other => panic!("Unexpected result {other:?}")
+        }
+        let requests = server.finish();
+        assert!(requests.iter().all(|request| request.method == "DELETE"));
+    }
+
+    #[test]
+    fn test_unauthorized () {
+        let server = TestServer::start(vec!(Reply::status(401, "Unauthorized", &[("WWW-Authenticate", "Basic realm=\"test\"")], "")));
+        let delete_result = get_davcontroller().delete(&server.url.join("secret.txt").unwrap());
+        assert!(matches!(delete_result, Err(DavCtrlError::Unauthorized(_))));
+    }
+
+    #[test]
+    fn test_reconnect_after_dropped_connection () {
+        let server = TestServer::start(vec!(
+            Reply::Close,
+            Reply::status(204, "No Content", &[], "")
+        ));
+        let delete_result = get_davcontroller().delete(&server.url.join("stale.txt").unwrap());
+        assert!(delete_result.is_ok(), "Error is {}", delete_result.err().unwrap());
+        let requests = server.finish();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|request| request.method == "DELETE" && request.path == "/stale.txt"));
+    }
+
+    #[test]
+    fn test_put_encodes_file_name () {
+        let server = TestServer::start(vec!(
+            Reply::status(201, "Created", &[], ""),
+            Reply::listing(&[("/dir/my%20report%20%231%20%C3%A4.txt", false)])
+        ));
+        let dir = Temp::new_dir().unwrap();
+        let file = dir.join("my report #1 ä.txt");
+        std::fs::write(&file, "content").unwrap();
+        let controller = get_davcontroller();
+        let collection = server.url.join("dir/").unwrap();
+        let results = controller.put(&vec!(file.as_path()), &collection);
+        assert!(results[0].is_ok(), "Error is {}", results[0].as_ref().err().unwrap());
+        let uploaded = DavController::member_url(&collection, "my report #1 ä.txt").unwrap();
+        let listing = controller.ls(&uploaded, Depth::Zero, &FilterCriteria::match_all()).unwrap();
+        assert_eq!(listing[0].name, "my report #1 ä.txt");
+        let requests = server.finish();
+        assert_eq!(requests[0].method, "PUT");
+        assert_eq!(requests[0].path, "/dir/my%20report%20%231%20%C3%A4.txt");
+        assert_eq!(requests[1].path, requests[0].path);
+    }
+
+    #[test]
+    fn test_no_retry_on_server_error () {
+        let server = TestServer::start(vec!(
+            Reply::status(500, "Internal Server Error", &[], ""),
+            Reply::status(204, "No Content", &[], "")
+        ));
+        let delete_result = get_davcontroller().delete(&server.url.join("broken.txt").unwrap());
+        assert!(delete_result.is_err());
+        assert_eq!(server.requests().len(), 1);
+    }
+    
     #[test]
     fn test_file_lifecycle () {
         let hello_url = get_testserver_url().join("hello%20world.txt").unwrap();
@@ -292,7 +2827,7 @@ mod tests {
         let temppath = tempthing.as_path();
         std::fs::write(temppath, "Hello world!\n").unwrap();
         let sources = vec!(temppath);
-        let put_result = get_davcontroller().put(&sources, &hello_url);
+        let put_result = get_davcontroller().put(&sources, hello_url);
         for result in put_result {
             assert!(result.is_ok(), "Error is {}", result.err().unwrap());
         }
@@ -300,29 +2835,29 @@ mod tests {
     
     fn subtest_ls_attr () {
         let dav_controller = get_davcontroller();
-        let filter_type = FilterCriteria::new("text/plain", "*", "*", "*", "*").unwrap();
-        let filter_size = FilterCriteria::new("*", "13", "13", "*", "*").unwrap();
-        let filter_modification = FilterCriteria::new("*", "*", "*", "2019-01-01T00:00:00+00:00", "2019-12-31T00:00:00+00:00").unwrap();
-        let mut listing_result = dav_controller.ls(&get_testserver_url(), &filter_type).unwrap();
+        let filter_type = FilterCriteria::new("text/plain", "*", "*", "*", "*", "*").unwrap();
+        let filter_size = FilterCriteria::new("*", "13", "13", "*", "*", "*").unwrap();
+        let filter_modification = FilterCriteria::new("*", "*", "*", "2019-01-01T00:00:00+00:00", "2019-12-31T00:00:00+00:00", "*").unwrap();
+        let mut listing_result = dav_controller.ls(&get_testserver_url(), Depth::One, &filter_type).unwrap();
         assert_eq!(listing_result.len(), 2);
-        listing_result = dav_controller.ls(&get_testserver_url(), &filter_size).unwrap();
+        listing_result = dav_controller.ls(&get_testserver_url(), Depth::One, &filter_size).unwrap();
         assert_eq!(listing_result.len(), 1);
-        listing_result = dav_controller.ls(&get_testserver_url(), &filter_modification).unwrap();
+        listing_result = dav_controller.ls(&get_testserver_url(), Depth::One, &filter_modification).unwrap();
         assert_eq!(listing_result.len(), 8);
     }
     
     fn subtest_delete(hello_url: &Url) {
-        let delete_result = get_davcontroller().delete(&hello_url);
+        let delete_result = get_davcontroller().delete(hello_url);
         assert!(delete_result.is_ok(), "error is {}", delete_result.err().unwrap());
     }
     
     fn subtest_ls_attr_after_delete () {
         let dav_controller = get_davcontroller();
-        let filter_type = FilterCriteria::new("text/plain", "*", "*", "*", "*").unwrap();
-        let filter_size = FilterCriteria::new("*", "13", "13", "*", "*").unwrap();
-        let mut listing_result = dav_controller.ls(&get_testserver_url(), &filter_type).unwrap();
+        let filter_type = FilterCriteria::new("text/plain", "*", "*", "*", "*", "*").unwrap();
+        let filter_size = FilterCriteria::new("*", "13", "13", "*", "*", "*").unwrap();
+        let mut listing_result = dav_controller.ls(&get_testserver_url(), Depth::One, &filter_type).unwrap();
         assert_eq!(listing_result.len(), 1);
-        listing_result = dav_controller.ls(&get_testserver_url(), &filter_size).unwrap();
+        listing_result = dav_controller.ls(&get_testserver_url(), Depth::One, &filter_size).unwrap();
         assert_eq!(listing_result.len(), 0);
     }
 
@@ -4,14 +4,16 @@
  * 
  * (c) 2024 Andreas Feldner
  */
-
 use chrono::DateTime;
 use chrono::offset::Utc;
 use chrono::ParseError;
+use std::collections::HashSet;
 use std::num::ParseIntError;
 use derive_more::{Display, From};
 use dateparser::DateTimeUtc;
 use crate::catalogue::CatalogueInfo;
+use rayon::prelude::*;
+use regex::{Error as RegexError, Regex, RegexBuilder};
 
 
 #[derive(Debug, Display, From)]
@@ -27,22 +29,203 @@ impl From<ParseError> for FilterCriteriaError {
     }
 }
 
+impl From<RegexError> for FilterCriteriaError {
+    fn from(regex_err: RegexError) -> Self{
+        FilterCriteriaError::ParseError(format!("invalid file type pattern: {regex_err}"))
+    }
+}
+
 impl From<ParseIntError> for FilterCriteriaError {
     fn from(parseerr: ParseIntError) -> Self{
         FilterCriteriaError::ParseError(parseerr.to_string())
     }
 }
 
+/// The metadata the filter criteria are evaluated on. Implement it to
+/// filter your own structures with `FilterCriteria`.
+pub trait Filterable {
+    fn name(&self) -> &str;
+    fn size(&self) -> Option<u64>;
+    /// Time of the last modification
+    fn date(&self) -> Option<DateTime<Utc>>;
+    fn created(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+    /// The base content type, like `text/plain`
+    fn file_type(&self) -> Option<&str>;
+    /// The parameters of the content type, like `charset=utf-8`
+    fn file_type_params(&self) -> &[(String, String)] {
+        &[]
+    }
+    fn is_collection(&self) -> bool {
+        false
+    }
+
+    /// Reconstructs the content type including its parameters.
+    fn full_content_type(&self) -> Option<String> {
+        let mut full_type = self.file_type()?.to_string();
+        for (name, value) in self.file_type_params() {
+            full_type.push_str(&format!("; {name}={value}"));
+        }
+        Some(full_type)
+    }
+}
+
+impl Filterable for CatalogueInfo {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    fn date(&self) -> Option<DateTime<Utc>> {
+        self.date.as_ref().map(|DateTimeUtc(date)| *date)
+    }
+
+    fn created(&self) -> Option<DateTime<Utc>> {
+        self.created.as_ref().map(|DateTimeUtc(created)| *created)
+    }
+
+    fn file_type(&self) -> Option<&str> {
+        self.file_type.as_deref()
+    }
+
+    fn file_type_params(&self) -> &[(String, String)] {
+        &self.file_type_params
+    }
+
+    fn is_collection(&self) -> bool {
+        self.is_collection
+    }
+}
+
+/// Criterion on the content type: either a regular expression the whole
+/// base type must match, or a list of base types one of which must be met
+/// exactly. Both ignore case unless asked not to, as servers send
+/// `Text/Plain` as well.
+enum TypeFilter {
+    Pattern(Regex),
+    OneOf {file_types: HashSet<String>, case_sensitive: bool}
+}
+
+impl TypeFilter {
+    fn new(file_type_desc: &str) -> Result<Self, FilterCriteriaError> {
+        if file_type_desc.contains(',') || Self::_is_plain_type(file_type_desc) {
+            Ok(TypeFilter::OneOf {
+                file_types: file_type_desc.split(',')
+                    .map(|file_type| file_type.trim().to_string())
+                    .filter(|file_type| !file_type.is_empty())
+                    .collect(),
+                case_sensitive: false
+            })
+        } else {
+            Ok(TypeFilter::Pattern(RegexBuilder::new(&format!("^(?:{file_type_desc})$")).case_insensitive(true).build()?))
+        }
+    }
+
+    /// Tells if the description is a content type like `image/svg+xml`,
+    /// which is meant literally, though `.` and `+` would be special in a
+    /// regular expression.
+    fn _is_plain_type(file_type_desc: &str) -> bool {
+        let is_name = |name: &str| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || "!#&-_.+".contains(c));
+        file_type_desc.split_once('/').is_some_and(|(main_type, sub_type)| is_name(main_type) && is_name(sub_type))
+    }
+
+    fn case_sensitive(self) -> Result<Self, FilterCriteriaError> {
+        match self {
+            TypeFilter::Pattern(regex) => Ok(TypeFilter::Pattern(Regex::new(regex.as_str())?)),
+            TypeFilter::OneOf {file_types, ..} => Ok(TypeFilter::OneOf {file_types, case_sensitive: true})
+        }
+    }
+
+    fn matches(&self, attrs: &impl Filterable) -> bool {
+        match self {
+            TypeFilter::Pattern(regex) => {
+                // match against the base type, unless the filter explicitly asks for parameters
+                let file_type = if regex.as_str().contains(';') {attrs.full_content_type()} else {attrs.file_type().map(str::to_string)};
+                file_type.is_some_and(|file_type| regex.is_match(&file_type))
+            },
+            TypeFilter::OneOf {file_types, case_sensitive: true} =>
+                attrs.file_type().is_some_and(|file_type| file_types.contains(file_type)),
+            TypeFilter::OneOf {file_types, case_sensitive: false} =>
+                attrs.file_type().is_some_and(|file_type| file_types.iter().any(|listed| listed.eq_ignore_ascii_case(file_type)))
+        }
+    }
+}
+
+/// Translates a shell-style glob on file names into an anchored regular
+/// expression: `*` stands for any number of characters, `?` for one, and
+/// `[...]` (negated by a leading `!` or `^`) for one of a set.
+fn _glob_to_regex(glob: &str) -> Result<Regex, FilterCriteriaError> {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => {
+                let mut class = String::new();
+                let mut closed = false;
+                for class_char in chars.by_ref() {
+                    match class_char {
+                        // a ] right at the start is part of the set, like in the shell
+                        ']' if !class.is_empty() && class != "!" && class != "^" => {
+                            closed = true;
+                            break;
+                        },
+                        // characters with a meaning in regex classes, but none in globs
+                        '\\' | '[' | ']' | '&' | '~' => {
+                            class.push('\\');
+                            class.push(class_char);
+                        },
+                        _ => class.push(class_char)
+                    }
+                }
+                if !closed {
+                    return Err(FilterCriteriaError::ParseError(format!("invalid name pattern {glob}: unclosed [")));
+                }
+                if let Some(negated) = class.strip_prefix('!') {
+                    class = format!("^{negated}");
+                }
+                regex.push('[');
+                regex.push_str(&class);
+                regex.push(']');
+            },
+            _ => regex.push_str(&regex::escape(&c.to_string()))
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).map_err(|error| FilterCriteriaError::ParseError(format!("invalid name pattern {glob}: {error}")))
+}
 
 pub struct FilterCriteria {
-    file_type: Option<String>,
+    file_type: Option<TypeFilter>,
+    /// Selects the entries the type filter does not match, given as `!<type>`
+    file_type_negated: bool,
+    name: Option<Regex>,
+    /// Selects the entries the name glob does not match, given as `!<glob>`
+    name_negated: bool,
     min_size: Option<u64>,
     max_size: Option<u64>,
     earliest_modification: Option<DateTime<Utc>>,
-    latest_modification: Option<DateTime<Utc>>
+    latest_modification: Option<DateTime<Utc>>,
+    created_after: Option<DateTime<Utc>>,
+    created_before: Option<DateTime<Utc>>,
+    files_only: bool,
+    /// Inverts the result of all criteria but `files_only`
+    negated: bool
 }
 
 macro_rules! parse_filter_desc {
+    ($desc:expr, parsed by $parser:expr) => {
+        if $desc == "*" {
+            None
+        } else {
+            Some($parser($desc)?)
+        }
+    };
     ($desc:expr, $resulttype:ty) => {
         if $desc == "*" {
             None
@@ -52,28 +235,125 @@ macro_rules! parse_filter_desc {
     }
 }
 
+/// Parses a size in bytes, optionally with a decimal (`K`, `M`, `G`, `T`,
+/// also followed by `B`) or binary (`KiB`, `MiB`, `GiB`, `TiB`) suffix,
+/// like `1.5M` or `10KiB`.
+fn _parse_size(size_desc: &str) -> Result<u64, FilterCriteriaError> {
+    let invalid = || FilterCriteriaError::ParseError(format!("invalid size {size_desc}"));
+    let split = size_desc.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(size_desc.len());
+    let (number, suffix) = size_desc.split_at(split);
+    let factor: u64 = match suffix.trim_start() {
+        "" | "B" => return number.parse().map_err(|_| invalid()),
+        "K" | "KB" | "k" | "kB" => 1000,
+        "M" | "MB" => 1000_u64.pow(2),
+        "G" | "GB" => 1000_u64.pow(3),
+        "T" | "TB" => 1000_u64.pow(4),
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        "TiB" => 1 << 40,
+        _ => return Err(invalid())
+    };
+    let value: f64 = number.parse().map_err(|_| invalid())?;
+    let bytes = (value * factor as f64).round();
+    if bytes > u64::MAX as f64 {
+        return Err(invalid());
+    }
+    Ok(bytes as u64)
+}
+
+/// Splits the negation off a description like `!image/.*`. A negated
+/// `*` is a `*` as well, as excluding everything is no useful filter.
+fn _split_negation(desc: &str) -> (&str, bool) {
+    match desc.strip_prefix('!') {
+        Some("*") => ("*", false),
+        Some(negated_desc) => (negated_desc, true),
+        None => (desc, false)
+    }
+}
+
 impl FilterCriteria {
+    /// Each description is `*` for no restriction. The type and the name
+    /// glob can be negated by a leading `!`, so `!image/.*` selects all
+    /// entries except images, including those without a type.
     pub fn new(file_type_desc: &str, 
            min_size_desc: &str, 
            max_size_desc: &str, 
            earliest_modification_desc: &str,
-           latest_modification_desc: &str) -> Result<Self, FilterCriteriaError> {
+           latest_modification_desc: &str,
+           name_desc: &str) -> Result<Self, FilterCriteriaError> {
+        let (file_type_desc, file_type_negated) = _split_negation(file_type_desc);
+        let (name_desc, name_negated) = _split_negation(name_desc);
         Ok(Self {
-            file_type: if file_type_desc=="*" {None} else {Some(file_type_desc.to_string())},
-            min_size: parse_filter_desc! (min_size_desc, u64),
-            max_size: parse_filter_desc! (max_size_desc, u64),
+            file_type: if file_type_desc=="*" {None} else {Some(TypeFilter::new(file_type_desc)?)},
+            file_type_negated,
+            name: if name_desc=="*" {None} else {Some(_glob_to_regex(name_desc)?)},
+            name_negated,
+            min_size: parse_filter_desc! (min_size_desc, parsed by _parse_size),
+            max_size: parse_filter_desc! (max_size_desc, parsed by _parse_size),
             earliest_modification: parse_filter_desc! (earliest_modification_desc, DateTime<Utc>),
-            latest_modification: parse_filter_desc! (latest_modification_desc, DateTime<Utc>)
+            latest_modification: parse_filter_desc! (latest_modification_desc, DateTime<Utc>),
+            created_after: None,
+            created_before: None,
+            files_only: false,
+            negated: false
         })
     }
+
+    /// Additionally restricts the creation date, given like the modification dates.
+    pub fn with_creation_range(mut self, created_after_desc: &str, created_before_desc: &str) -> Result<Self, FilterCriteriaError> {
+        self.created_after = parse_filter_desc! (created_after_desc, DateTime<Utc>);
+        self.created_before = parse_filter_desc! (created_before_desc, DateTime<Utc>);
+        Ok(self)
+    }
     
+    /// Additionally rejects all collections, which often have neither size
+    /// nor content type to tell them from files.
+    pub fn files_only(mut self) -> Self {
+        self.files_only = true;
+        self
+    }
+
+    /// Lets the case of the content type matter, which is ignored by default.
+    pub fn case_sensitive_types(mut self) -> Result<Self, FilterCriteriaError> {
+        self.file_type = self.file_type.map(TypeFilter::case_sensitive).transpose()?;
+        Ok(self)
+    }
+
+    /// Selects the entries not meeting the criteria instead, like `--not`
+    /// does; collections are still rejected with `files_only`. Criteria
+    /// without any restriction stay the same, rather than matching nothing.
+    pub fn negated(mut self) -> Self {
+        self.negated = true;
+        self
+    }
+
     pub fn match_all() -> Self {
-        Self {file_type: None, min_size: None, max_size: None, earliest_modification: None, latest_modification: None}
+        Self {file_type: None, file_type_negated: false, name: None, name_negated: false, min_size: None, max_size: None,
+            earliest_modification: None, latest_modification: None, created_after: None, created_before: None,
+            files_only: false, negated: false}
+    }
+
+    fn _restricts(&self) -> bool {
+        self.file_type.is_some() || self.name.is_some() || self.min_size.is_some() || self.max_size.is_some()
+            || self.earliest_modification.is_some() || self.latest_modification.is_some()
+            || self.created_after.is_some() || self.created_before.is_some()
     }
     
-    pub fn matches(&self, attrs: &CatalogueInfo) -> bool {
-        if let Some(size) = attrs.size {
-            if size > self.max_size.unwrap_or(u64::max_value()) {
+    pub fn matches(&self, attrs: &impl Filterable) -> bool {
+        if self.files_only && attrs.is_collection() {
+            return false;
+        }
+        if self.negated && self._restricts() {
+            !self._meets_criteria(attrs)
+        } else {
+            self._meets_criteria(attrs)
+        }
+    }
+
+    fn _meets_criteria(&self, attrs: &impl Filterable) -> bool {
+        if let Some(size) = attrs.size() {
+            if size > self.max_size.unwrap_or(u64::MAX) {
                 return false;
             }
             if size < self.min_size.unwrap_or(0) {
@@ -83,25 +363,351 @@ impl FilterCriteria {
             // attrs doesn't have a size attribute which is queried
             return false;
         }
-        if let Some(DateTimeUtc(creation_date)) = attrs.date {
-            if creation_date < self.earliest_modification.unwrap_or(creation_date) {
+        if let Some(modification_date) = attrs.date() {
+            if modification_date < self.earliest_modification.unwrap_or(modification_date) {
                 return false;
             }
-            if creation_date > self.latest_modification.unwrap_or(creation_date) {
+            if modification_date > self.latest_modification.unwrap_or(modification_date) {
                 return false;
             }
         } else if self.earliest_modification.is_some() || self.latest_modification.is_some() {
             return false;
         }
-        if let Some(regex) = self.file_type.as_ref() {
-            if let Some(file_type) = attrs.file_type.as_ref() {
-                return regex.find(file_type).is_some();
-            } else {
-                // there's a filter on file_type, but this entry doesn't have a type
+        if let Some(created) = attrs.created() {
+            if created < self.created_after.unwrap_or(created) {
+                return false;
+            }
+            if created > self.created_before.unwrap_or(created) {
                 return false;
             }
+        } else if self.created_after.is_some() || self.created_before.is_some() {
+            return false;
+        }
+        if self.name.as_ref().is_some_and(|name| name.is_match(attrs.name()) == self.name_negated) {
+            return false;
+        }
+        if let Some(type_filter) = self.file_type.as_ref() {
+            // if there's a filter on file_type, but this entry doesn't have a type, it doesn't match
+            return type_filter.matches(attrs) != self.file_type_negated;
         }
         true
     }
 
+    /// Keeps only the matching entries, preserving their order. The criteria
+    /// are evaluated in parallel, which pays off for huge directories.
+    pub fn filter<T: Filterable + Send>(&self, entries: Vec<T>) -> Vec<T> {
+        entries.into_par_iter()
+            .filter(|attrs| self.matches(attrs))
+            .collect()
+    }
+}
+
+/// Alternatives of filter criteria: an entry matches if it meets all
+/// criteria of at least one of them. So the criteria of an alternative
+/// are ANDed, and the alternatives ORed; a set of no alternatives
+/// matches nothing.
+pub struct FilterSet {
+    alternatives: Vec<FilterCriteria>
+}
+
+impl FilterSet {
+    pub fn new(alternatives: Vec<FilterCriteria>) -> Self {
+        Self {alternatives}
+    }
+
+    pub fn matches(&self, attrs: &impl Filterable) -> bool {
+        self.alternatives.iter().any(|criteria| criteria.matches(attrs))
+    }
+
+    /// Keeps only the matching entries, preserving their order.
+    pub fn filter<T: Filterable + Send>(&self, entries: Vec<T>) -> Vec<T> {
+        entries.into_par_iter()
+            .filter(|attrs| self.matches(attrs))
+            .collect()
+    }
+}
+
+impl From<FilterCriteria> for FilterSet {
+    fn from(criteria: FilterCriteria) -> Self {
+        Self::new(vec!(criteria))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use url::Url;
+
+    fn entry_with_type(file_type: &str, params: Vec<(&str, &str)>) -> CatalogueInfo {
+        CatalogueInfo {
+            url: Url::parse("https://example.com/file").unwrap(),
+            name: "file".to_string(),
+            display_name: None,
+            size: None,
+            date: None,
+            created: None,
+            file_type: Some(file_type.to_string()),
+            file_type_params: params.into_iter().map(|(n, v)| (n.to_string(), v.to_string())).collect(),
+            is_collection: false,
+            etag: None,
+            quota_used_bytes: None,
+            quota_available_bytes: None
+        }
+    }
+
+    #[test]
+    fn test_filter_is_shareable () {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<FilterCriteria>();
+    }
+
+    #[test]
+    fn test_filter_large_catalogue_preserves_order () {
+        let types = ["text/plain", "image/png", "application/vnd.oasis.opendocument.text", "image/jpeg"];
+        let entries: Vec<CatalogueInfo> = (0..50_000).map(|number| {
+            let mut entry = entry_with_type(types[number % types.len()], vec!());
+            entry.name = format!("file{number}");
+            entry
+        }).collect();
+        let filter = FilterCriteria::new(".*opendocument.*", "*", "*", "*", "*", "*").unwrap();
+        let matching = filter.filter(entries);
+        assert_eq!(matching.len(), 12_500);
+        let names: Vec<usize> = matching.iter().map(|entry| entry.name[4..].parse().unwrap()).collect();
+        assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(names[0], 2);
+    }
+
+    #[test]
+    fn test_creation_range () {
+        let filter = FilterCriteria::new("*", "*", "*", "*", "*", "*").unwrap()
+            .with_creation_range("2020-01-01T00:00:00Z", "*").unwrap();
+        let mut entry = entry_with_type("application/pdf", vec!());
+        assert!(!filter.matches(&entry));
+        entry.created = Some("2019-12-31T23:00:00Z".parse().unwrap());
+        assert!(!filter.matches(&entry));
+        entry.created = Some("2021-06-01T00:00:00Z".parse().unwrap());
+        assert!(filter.matches(&entry));
+        // the modification date is not affected
+        entry.date = Some("2019-12-31T23:00:00Z".parse().unwrap());
+        assert!(filter.matches(&entry));
+    }
+
+    struct LocalFile {
+        name: String,
+        size: u64
+    }
+
+    impl Filterable for LocalFile {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn size(&self) -> Option<u64> {
+            Some(self.size)
+        }
+
+        fn date(&self) -> Option<DateTime<Utc>> {
+            None
+        }
+
+        fn file_type(&self) -> Option<&str> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_filter_own_structures () {
+        let filter = FilterCriteria::new("*", "10", "*", "*", "*", "*").unwrap();
+        let files = vec!(LocalFile {name: "small".to_string(), size: 5}, LocalFile {name: "big".to_string(), size: 50});
+        let matching = filter.filter(files);
+        assert_eq!(matching.iter().map(|file| file.name()).collect::<Vec<_>>(), vec!("big"));
+    }
+
+    #[test]
+    fn test_type_filter_ignores_parameters () {
+        let filter = FilterCriteria::new("text/plain", "*", "*", "*", "*", "*").unwrap();
+        assert!(filter.matches(&entry_with_type("text/plain", vec!(("charset", "utf-8")))));
+        assert!(!filter.matches(&entry_with_type("image/png", vec!())));
+    }
+
+    #[test]
+    fn test_type_filter_with_list () {
+        let filter = FilterCriteria::new("image/png,image/jpeg, image/gif", "*", "*", "*", "*", "*").unwrap();
+        assert!(filter.matches(&entry_with_type("image/png", vec!())));
+        assert!(filter.matches(&entry_with_type("image/gif", vec!())));
+        assert!(filter.matches(&entry_with_type("image/jpeg", vec!(("quality", "high")))));
+        assert!(!filter.matches(&entry_with_type("image/svg+xml", vec!())));
+        // list elements must equal the base type, not just be contained
+        assert!(!filter.matches(&entry_with_type("image/pngx", vec!())));
+    }
+
+    #[test]
+    fn test_type_filter_with_regex () {
+        let filter = FilterCriteria::new("text/.*", "*", "*", "*", "*", "*").unwrap();
+        assert!(filter.matches(&entry_with_type("text/plain", vec!())));
+        assert!(filter.matches(&entry_with_type("text/html", vec!())));
+        assert!(!filter.matches(&entry_with_type("image/png", vec!())));
+        // the whole type has to match
+        assert!(!FilterCriteria::new("text", "*", "*", "*", "*", "*").unwrap().matches(&entry_with_type("application/context+xml", vec!())));
+        assert!(!FilterCriteria::new("image/", "*", "*", "*", "*", "*").unwrap().matches(&entry_with_type("image/png", vec!())));
+        // a type matches itself, even with characters special in regular expressions
+        let filter = FilterCriteria::new("image/svg+xml", "*", "*", "*", "*", "*").unwrap();
+        assert!(filter.matches(&entry_with_type("image/svg+xml", vec!())));
+        assert!(filter.matches(&entry_with_type("Image/SVG+XML", vec!())));
+        assert!(!filter.matches(&entry_with_type("image/svgxml", vec!())));
+        let filter = FilterCriteria::new("image/(png|gif)", "*", "*", "*", "*", "*").unwrap();
+        assert!(filter.matches(&entry_with_type("image/gif", vec!())));
+        assert!(!filter.matches(&entry_with_type("image/gifx", vec!())));
+        // an entry without type matches no type filter
+        let mut typeless = entry_with_type("text/plain", vec!());
+        typeless.file_type = None;
+        assert!(!filter.matches(&typeless));
+        assert!(FilterCriteria::new("*", "*", "*", "*", "*", "*").unwrap().matches(&typeless));
+    }
+
+    #[test]
+    fn test_invalid_type_pattern () {
+        assert!(matches!(FilterCriteria::new("text/(plain", "*", "*", "*", "*", "*"), Err(FilterCriteriaError::ParseError(_))));
+    }
+
+    #[test]
+    fn test_size_with_suffix () {
+        let mut entry = entry_with_type("application/pdf", vec!());
+        entry.size = Some(1000);
+        assert!(FilterCriteria::new("*", "1K", "1K", "*", "*", "*").unwrap().matches(&entry));
+        assert!(!FilterCriteria::new("*", "1KiB", "*", "*", "*", "*").unwrap().matches(&entry));
+        assert!(FilterCriteria::new("*", "1000", "1000", "*", "*", "*").unwrap().matches(&entry));
+        entry.size = Some(1_500_000);
+        assert!(FilterCriteria::new("*", "1.5M", "1.5MB", "*", "*", "*").unwrap().matches(&entry));
+        assert!(!FilterCriteria::new("*", "*", "1.4M", "*", "*", "*").unwrap().matches(&entry));
+        assert_eq!(_parse_size("2G").unwrap(), 2_000_000_000);
+        assert_eq!(_parse_size("5MiB").unwrap(), 5_242_880);
+    }
+
+    #[test]
+    fn test_invalid_size () {
+        for size_desc in ["lots", "5X", "1.5", "1.2.3K", "K", "-1"] {
+            match FilterCriteria::new("*", size_desc, "*", "*", "*", "*") {
+                Err(FilterCriteriaError::ParseError(reason)) => assert!(reason.contains(size_desc), "reason is {reason}"),
+                _ => panic!("Size {size_desc} accepted")
+            }
+        }
+    }
+
+    #[test]
+    fn test_files_only () {
+        let mut entry = entry_with_type("httpd/unix-directory", vec!());
+        entry.is_collection = true;
+        let filter = FilterCriteria::new("*", "*", "*", "*", "*", "*").unwrap();
+        assert!(filter.matches(&entry));
+        let filter = filter.files_only();
+        assert!(!filter.matches(&entry));
+        entry.is_collection = false;
+        assert!(filter.matches(&entry));
+        assert!(!FilterCriteria::match_all().files_only().matches(&CatalogueInfo {is_collection: true, ..entry}));
+    }
+
+    #[test]
+    fn test_name_filter () {
+        let mut entry = entry_with_type("text/plain", vec!());
+        let cases = [("*.txt", "notes.txt", true), ("*.txt", "notes.txt.bak", false), ("foo?", "foo1", true),
+            ("foo?", "foo", false), ("[ab]*", "beta", true), ("[!ab]*", "beta", false), ("a.b", "axb", false),
+            ("report (1)*", "report (1).pdf", true), ("[]x]", "]", true)];
+        for (glob, name, expected) in cases {
+            entry.name = name.to_string();
+            let filter = FilterCriteria::new("*", "*", "*", "*", "*", glob).unwrap();
+            assert_eq!(filter.matches(&entry), expected, "{glob} on {name}");
+        }
+        // all criteria have to be met
+        entry.name = "notes.txt".to_string();
+        assert!(!FilterCriteria::new("image/png", "*", "*", "*", "*", "*.txt").unwrap().matches(&entry));
+    }
+
+    #[test]
+    fn test_invalid_name_pattern () {
+        match FilterCriteria::new("*", "*", "*", "*", "*", "[abc") {
+            Err(FilterCriteriaError::ParseError(reason)) => assert!(reason.contains("[abc"), "reason is {reason}"),
+            _ => panic!("Unclosed [ accepted")
+        }
+    }
+
+    #[test]
+    fn test_type_filter_with_parameters () {
+        let filter = FilterCriteria::new("charset=utf-8", "*", "*", "*", "*", "*").unwrap();
+        // without a ';' the filter only sees the base type
+        assert!(!filter.matches(&entry_with_type("text/plain", vec!(("charset", "utf-8")))));
+        let filter = FilterCriteria::new("text/plain;.*", "*", "*", "*", "*", "*").unwrap();
+        assert!(filter.matches(&entry_with_type("text/plain", vec!(("charset", "utf-8")))));
+    }
+
+    #[test]
+    fn test_filter_set_is_union () {
+        let mut big_image = entry_with_type("image/png", vec!());
+        big_image.size = Some(20_000_000);
+        let mut small_image = entry_with_type("image/png", vec!());
+        small_image.size = Some(1000);
+        let mut text = entry_with_type("text/plain", vec!());
+        text.size = Some(1000);
+        let texts_or_big = FilterSet::new(vec!(
+            FilterCriteria::new("text/.*", "*", "*", "*", "*", "*").unwrap(),
+            FilterCriteria::new("*", "10M", "*", "*", "*", "*").unwrap()
+        ));
+        assert!(texts_or_big.matches(&text));
+        assert!(texts_or_big.matches(&big_image));
+        assert!(!texts_or_big.matches(&small_image));
+        assert_eq!(texts_or_big.filter(vec!(small_image, text, big_image)).len(), 2);
+        // a single alternative is the same as the criteria on their own
+        let mut text = entry_with_type("text/plain", vec!());
+        assert!(FilterSet::from(FilterCriteria::new("text/.*", "*", "*", "*", "*", "*").unwrap()).matches(&text));
+        text.file_type = None;
+        assert!(!FilterSet::from(FilterCriteria::new("text/.*", "*", "*", "*", "*", "*").unwrap()).matches(&text));
+        assert!(!FilterSet::new(vec!()).matches(&text));
+    }
+
+    #[test]
+    fn test_negated_type_filter () {
+        let filter = FilterCriteria::new("!image/.*", "*", "*", "*", "*", "*").unwrap();
+        assert!(!filter.matches(&entry_with_type("image/png", vec!())));
+        assert!(filter.matches(&entry_with_type("text/plain", vec!())));
+        // an entry without type is no image either
+        let mut typeless = entry_with_type("text/plain", vec!());
+        typeless.file_type = None;
+        assert!(filter.matches(&typeless));
+        let filter = FilterCriteria::new("!image/png,image/gif", "*", "*", "*", "*", "!*.txt").unwrap();
+        assert!(!filter.matches(&entry_with_type("image/gif", vec!())));
+        let mut entry = entry_with_type("image/jpeg", vec!());
+        assert!(filter.matches(&entry));
+        entry.name = "notes.txt".to_string();
+        assert!(!filter.matches(&entry));
+        // negating * excludes nothing
+        assert!(FilterCriteria::new("!*", "*", "*", "*", "*", "!*").unwrap().matches(&typeless));
+    }
+
+    #[test]
+    fn test_negated_criteria () {
+        let mut entry = entry_with_type("text/plain", vec!());
+        entry.date = Some("2023-06-01T00:00:00Z".parse().unwrap());
+        let not_this_year = FilterCriteria::new("*", "*", "*", "2024-01-01T00:00:00Z", "*", "*").unwrap().negated();
+        assert!(not_this_year.matches(&entry));
+        entry.date = Some("2024-06-01T00:00:00Z".parse().unwrap());
+        assert!(!not_this_year.matches(&entry));
+        // without restrictions, negation changes nothing
+        assert!(FilterCriteria::match_all().negated().matches(&entry));
+        entry.is_collection = true;
+        assert!(!FilterCriteria::new("image/png", "*", "*", "*", "*", "*").unwrap().negated().files_only().matches(&entry));
+    }
+
+    #[test]
+    fn test_type_filter_ignores_case () {
+        let upper_case = entry_with_type("TEXT/PLAIN", vec!());
+        assert!(FilterCriteria::new("text/plain", "*", "*", "*", "*", "*").unwrap().matches(&upper_case));
+        assert!(FilterCriteria::new("text/p.*", "*", "*", "*", "*", "*").unwrap().matches(&upper_case));
+        assert!(FilterCriteria::new("text/plain,text/html", "*", "*", "*", "*", "*").unwrap().matches(&upper_case));
+        assert!(!FilterCriteria::new("text/plain", "*", "*", "*", "*", "*").unwrap()
+            .case_sensitive_types().unwrap().matches(&upper_case));
+        assert!(!FilterCriteria::new("text/plain,text/html", "*", "*", "*", "*", "*").unwrap()
+            .case_sensitive_types().unwrap().matches(&upper_case));
+        assert!(FilterCriteria::new("TEXT/PLAIN", "*", "*", "*", "*", "*").unwrap()
+            .case_sensitive_types().unwrap().matches(&upper_case));
+    }
 }
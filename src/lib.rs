@@ -0,0 +1,21 @@
+/**
+ * Library entry of corroded_dav_cli, allowing the DAV and command
+ * controllers to be embedded in other programs.
+ * 
+ * (c) 2024 Andreas Feldner
+ */
+pub mod filter;
+pub mod catalogue;
+pub mod compare;
+pub mod completion;
+pub mod config;
+pub mod davclient;
+pub mod davctrl;
+pub mod interactive;
+pub mod output;
+pub mod progress;
+pub mod selection;
+pub mod transfer;
+
+#[cfg(test)]
+mod testserver;
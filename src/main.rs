@@ -3,43 +3,199 @@
  * 
  * (c) 2023 Andreas Feldner
  */
-mod filter;
-mod catalogue;
-mod davctrl;
-mod interactive;
-
-use std::io::{BufReader, Error as IoError, ErrorKind};
-use rustyline::DefaultEditor;
+use rustyline::Editor;
 use netrc::Netrc;
+use env_logger::Env;
+use log::Level;
 use std::env;
-use std::fs::File;
-use interactive::DavCmdController;
+use std::io::{stdin, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use corroded_dav_cli::config::{self, ConfigError};
+use corroded_dav_cli::davctrl::{PartialDownloadPolicy, ResponseLimits};
+use corroded_dav_cli::interactive::{DavCmdController, SessionOutcome, USAGE_EXIT_CODE};
+use corroded_dav_cli::output::OutputFormat;
 
-fn read_netrc() -> Result<Netrc, IoError> {
-    #[allow(deprecated)]
-    // honestly, I don't care where you have to place .netrc if you run this on cygwin under Windows
-    let home = env::home_dir().ok_or(IoError::from(ErrorKind::NotFound))?;
-    let path = home.join(".netrc");
-    if path.is_file() {
-        let netrc = File::open(path)?;
-        Netrc::parse(BufReader::new(netrc)).map_err(|_e| IoError::from(ErrorKind::InvalidInput))
-    } else {
-        Err(IoError::from(ErrorKind::NotFound))
+/// Reads the .netrc given, or ~/.netrc; only a missing ~/.netrc silently
+/// results in empty credentials.
+fn load_netrc(explicit_path: Option<PathBuf>, strict: bool) -> Netrc {
+    let explicit = explicit_path.is_some();
+    let Some(path) = explicit_path.or_else(config::default_netrc_path) else {
+        return Netrc::default();
+    };
+    match config::read_netrc(&path) {
+        Ok(netrc) => netrc,
+        Err(ConfigError::NotFound(_)) if !explicit => Netrc::default(),
+        Err(error @ ConfigError::NotFound(_)) => {
+            eprintln!("Error: {error}");
+            process::exit(1);
+        },
+        Err(error) if strict => {
+            eprintln!("Error: {error}");
+            process::exit(1);
+        },
+        Err(error) => {
+            eprintln!("Warning: ignoring credentials, {error}");
+            Netrc::default()
+        }
     }
 }
 
+/// Logs warnings, and the informational messages of corroded_dav_cli,
+/// to stderr unless RUST_LOG says otherwise, e.g. RUST_LOG=debug. The
+/// levels users see by default are written like messages, not log lines.
+fn init_logger() {
+    env_logger::Builder::from_env(Env::default().default_filter_or("warn,corroded_dav_cli=info"))
+        .format(|buf, record| match record.level() {
+            Level::Info => writeln!(buf, "{}", record.args()),
+            Level::Warn => writeln!(buf, "Warning: {}", record.args()),
+            Level::Error => writeln!(buf, "Error: {}", record.args()),
+            level => writeln!(buf, "{level} {}: {}", record.target(), record.args())
+        })
+        .init();
+}
+
 fn main() {
-    let netrc = read_netrc().unwrap_or(Netrc::default());
-    
+    init_logger();
     // parse cmd line args to find out if we're going to run interactive
-    //...
+    let mut allow_raw = false;
+    let mut strict_netrc = false;
+    let mut netrc_path = None;
+    let mut quiet = false;
+    let mut verbose = false;
+    let mut netrc_suffix_match = false;
+    let mut unlimited_downloads = false;
+    let mut partial_download_policy = PartialDownloadPolicy::default();
+    let mut command_sequence: Option<String> = None;
+    let mut command_words: Vec<String> = Vec::new();
+    let mut verify_size = false;
+    let mut output_format = OutputFormat::default();
+    let mut concurrency = 1;
+    let mut limits = ResponseLimits::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--allow-raw" => allow_raw = true,
+            "--strict-netrc" => strict_netrc = true,
+            "--netrc" => {
+                let Some(path) = args.next() else {
+                    eprintln!("Option --netrc requires the path of the file");
+                    process::exit(USAGE_EXIT_CODE);
+                };
+                netrc_path = Some(PathBuf::from(path));
+            },
+            "--quiet" => quiet = true,
+            "--verbose" => verbose = true,
+            "--netrc-suffix-match" => netrc_suffix_match = true,
+            "--max-response-bytes" => {
+                let Some(max_bytes) = args.next().and_then(|value| value.parse().ok()) else {
+                    eprintln!("Option --max-response-bytes requires a number of bytes");
+                    process::exit(USAGE_EXIT_CODE);
+                };
+                limits = ResponseLimits {metadata: max_bytes, download: Some(max_bytes)};
+            },
+            "--unlimited-downloads" => unlimited_downloads = true,
+            "--verify-size" => verify_size = true,
+            "--format" => {
+                let Some(format) = args.next().as_deref().and_then(OutputFormat::parse) else {
+                    eprintln!("Option --format requires one of human, json, csv or ndjson");
+                    process::exit(USAGE_EXIT_CODE);
+                };
+                output_format = format;
+            },
+            "--parallel" => {
+                let Some(parallel) = args.next().and_then(|value| value.parse().ok()).filter(|parallel| *parallel > 0) else {
+                    eprintln!("Option --parallel requires a positive number of transfers");
+                    process::exit(USAGE_EXIT_CODE);
+                };
+                concurrency = parallel;
+            },
+            "-c" => {
+                let Some(commands) = args.next() else {
+                    eprintln!("Option -c requires the commands to run");
+                    process::exit(USAGE_EXIT_CODE);
+                };
+                command_sequence = Some(commands);
+            },
+            "--on-error" => partial_download_policy = match args.next().as_deref() {
+                Some("keep") => PartialDownloadPolicy::Keep,
+                Some("delete") => PartialDownloadPolicy::Delete,
+                _ => {
+                    eprintln!("Option --on-error requires keep or delete");
+                    process::exit(USAGE_EXIT_CODE);
+                }
+            },
+            unknown if unknown.starts_with('-') => {
+                eprintln!("Unknown option {unknown}");
+                process::exit(USAGE_EXIT_CODE);
+            },
+            // the first word that is no option starts a command to run instead of a session
+            _ => {
+                command_words.push(arg);
+                command_words.extend(args.by_ref());
+            }
+        }
+    }
+    if unlimited_downloads {
+        limits.download = None;
+    }
+    let netrc = load_netrc(netrc_path.or_else(config::explicit_netrc_path), strict_netrc);
     // if we're interactive, run a DavCmdController with an interactive editor
-    let mut readline = DefaultEditor::new().unwrap(); // nothing useful to do if editor not constructable
     let mut session_controller = DavCmdController::new(netrc);
+    session_controller.set_allow_raw(allow_raw);
+    session_controller.set_quiet(quiet);
+    session_controller.set_verbose(verbose);
+    session_controller.set_netrc_suffix_match(netrc_suffix_match);
+    session_controller.set_response_limits(limits);
+    session_controller.set_partial_download_policy(partial_download_policy);
+    session_controller.set_verify_size(verify_size);
+    session_controller.set_output_format(output_format);
+    session_controller.set_concurrency(concurrency);
+    if let Some(ca_file) = env::var_os(config::CA_FILE_ENV).filter(|path| !path.is_empty()) {
+        if let Err(error) = session_controller.set_ca_certificate(Some(Path::new(&ca_file))) {
+            eprintln!("Error: cannot use the CA certificate of {}: {error}", config::CA_FILE_ENV);
+            process::exit(1);
+        }
+    }
+    session_controller.set_accept_invalid_certs(env::var_os(config::TLS_INSECURE_ENV).is_some_and(|value| value == "1"));
+    if !command_words.is_empty() {
+        if command_sequence.is_some() {
+            eprintln!("Either give a command or a sequence with -c, not both");
+            process::exit(USAGE_EXIT_CODE);
+        }
+        command_sequence = Some(DavCmdController::quote_command_line(&command_words));
+    }
+    if let Some(commands) = command_sequence {
+        let failures = session_controller.run_sequence(&commands);
+        process::exit(session_controller.exit_code(&failures));
+    }
+    let mut readline = Editor::new().unwrap(); // nothing useful to do if editor not constructable
+    readline.set_helper(Some(session_controller.path_completer()));
+    let history_path = config::history_path();
+    if let Some(path) = history_path.as_ref().filter(|path| path.exists()) {
+        if let Err(error) = readline.load_history(path) {
+            eprintln!("Warning: cannot read history {}: {error}", path.display());
+        }
+    }
     println!("Entering interactive session, ready for your commands");
-    let interactive_result = session_controller.run(&mut readline);
-    if let Err(error) = interactive_result {
-        eprintln!("Interactive session aborted with error {error}");
+    let interactive_result = session_controller.run_collecting_errors(&mut readline);
+    // the session also ends like this on end of input, e.g. Ctrl-D
+    if let Some(path) = &history_path {
+        if let Err(error) = readline.save_history(path) {
+            eprintln!("Warning: cannot save history {}: {error}", path.display());
+        }
     }
+    let exit_code = match interactive_result {
+        Err(error) => {
+            eprintln!("Interactive session aborted with error {error}");
+            SessionOutcome::Failed.exit_code()
+        },
+        // commands piped in by a script need to tell whether any of them failed
+        Ok(failures) if !stdin().is_terminal() => session_controller.exit_code(&failures),
+        Ok(_) => 0
+    };
     println!("Interactive session finished, bye.");
+    if exit_code != 0 {
+        process::exit(exit_code);
+    }
 }
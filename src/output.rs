@@ -0,0 +1,202 @@
+/**
+ * Defines the machine readable output formats of listings, and how
+ * sizes are shown to humans.
+ *
+ * (c) 2024 Andreas Feldner
+ */
+use std::fmt;
+use std::io::{Error as IoError, Write};
+use dateparser::DateTimeUtc;
+use serde_json::{json, Value};
+use crate::catalogue::CatalogueInfo;
+
+/// The format listings are written in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Columns for reading on a terminal
+    #[default]
+    Human,
+    /// One JSON array of all entries
+    Json,
+    /// Comma separated values, with a header line
+    Csv,
+    /// One JSON object per line
+    Ndjson
+}
+
+impl OutputFormat {
+    pub fn parse(format_desc: &str) -> Option<OutputFormat> {
+        match format_desc {
+            "human" => Some(OutputFormat::Human),
+            "json" => Some(OutputFormat::Json),
+            "csv" => Some(OutputFormat::Csv),
+            "ndjson" => Some(OutputFormat::Ndjson),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Human => "human",
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Ndjson => "ndjson"
+        })
+    }
+}
+
+/// The fields of each entry: first those every consumer may rely on, then
+/// the further attributes.
+const CSV_HEADER: [&str; 10] = ["url", "name", "size", "date", "file_type", "display_name", "created", "content_type", "collection", "etag"];
+
+fn _format_date(date: &Option<DateTimeUtc>) -> Option<String> {
+    date.as_ref().map(|DateTimeUtc(date)| date.to_rfc3339())
+}
+
+fn _json_value(attrs: &CatalogueInfo) -> Value {
+    json!({
+        "url": attrs.url.as_str(),
+        "name": attrs.name,
+        "size": attrs.size,
+        "date": _format_date(&attrs.date),
+        "file_type": attrs.file_type,
+        "display_name": attrs.display_name,
+        "created": _format_date(&attrs.created),
+        "content_type": attrs.full_content_type(),
+        "collection": attrs.is_collection,
+        "etag": attrs.etag
+    })
+}
+
+/// A number of bytes for humans like `ls -h` shows it, in powers of 1024
+/// with one decimal below 10, e.g. `512K`, `1.5M` or `4.0G`.
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [char; 6] = ['K', 'M', 'G', 'T', 'P', 'E'];
+    if bytes < 1024 {
+        return bytes.to_string();
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    // rounding must not end up at 1024 of a unit
+    while size.round() >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if size < 9.95 {
+        format!("{size:.1}{}", UNITS[unit])
+    } else {
+        format!("{size:.0}{}", UNITS[unit])
+    }
+}
+
+/// Quotes a CSV field if needed, as described in RFC 4180.
+fn _csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Writes entries in one of the machine readable formats. The human
+/// readable format is up to the caller, so this writes nothing for it.
+pub fn write_catalogue<'a>(format: OutputFormat, catalogue: impl IntoIterator<Item = &'a CatalogueInfo>, out: &mut impl Write) -> Result<(), IoError> {
+    match format {
+        OutputFormat::Human => (),
+        OutputFormat::Json => {
+            let entries: Vec<Value> = catalogue.into_iter().map(_json_value).collect();
+            serde_json::to_writer_pretty(&mut *out, &entries)?;
+            writeln!(out)?;
+        },
+        OutputFormat::Ndjson => {
+            for attrs in catalogue {
+                serde_json::to_writer(&mut *out, &_json_value(attrs))?;
+                writeln!(out)?;
+            }
+        },
+        OutputFormat::Csv => {
+            writeln!(out, "{}", CSV_HEADER.join(","))?;
+            for attrs in catalogue {
+                let fields = [
+                    attrs.url.to_string(),
+                    attrs.name.clone(),
+                    attrs.size.map(|size| size.to_string()).unwrap_or_default(),
+                    _format_date(&attrs.date).unwrap_or_default(),
+                    attrs.file_type.clone().unwrap_or_default(),
+                    attrs.display_name.clone().unwrap_or_default(),
+                    _format_date(&attrs.created).unwrap_or_default(),
+                    attrs.full_content_type().unwrap_or_default(),
+                    attrs.is_collection.to_string(),
+                    attrs.etag.clone().unwrap_or_default()
+                ];
+                let line: Vec<String> = fields.iter().map(|field| _csv_field(field)).collect();
+                writeln!(out, "{}", line.join(","))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use url::Url;
+
+    fn catalogue() -> Vec<CatalogueInfo> {
+        let base = Url::parse("https://example.com/dir/").unwrap();
+        let response = r#"<response xmlns="DAV:"><href>/dir/a,%22b%22.txt</href><propstat><prop>
+            <getcontentlength>42</getcontentlength><getcontenttype>text/plain; charset=utf-8</getcontenttype><getetag>"e1"</getetag>
+            <getlastmodified>Mon, 01 Jan 2024 10:00:00 GMT</getlastmodified>
+            </prop><status>HTTP/1.1 200 OK</status></propstat></response>"#.parse().unwrap();
+        vec!(CatalogueInfo::new(&base, &response))
+    }
+
+    fn written(format: OutputFormat) -> String {
+        let mut out = Vec::new();
+        write_catalogue(format, &catalogue(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_json () {
+        let entries: Value = serde_json::from_str(&written(OutputFormat::Json)).unwrap();
+        assert_eq!(entries[0]["name"], "a,\"b\".txt");
+        assert_eq!(entries[0]["size"], 42);
+        assert_eq!(entries[0]["date"], "2024-01-01T10:00:00+00:00");
+        assert_eq!(entries[0]["file_type"], "text/plain");
+        assert_eq!(entries[0]["content_type"], "text/plain; charset=utf-8");
+        assert_eq!(entries[0]["created"], Value::Null);
+        assert_eq!(entries[0]["display_name"], Value::Null);
+        let line: Value = serde_json::from_str(written(OutputFormat::Ndjson).trim_end()).unwrap();
+        assert_eq!(line, entries[0]);
+    }
+
+    #[test]
+    fn test_csv () {
+        let csv = written(OutputFormat::Csv);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "url,name,size,date,file_type,display_name,created,content_type,collection,etag");
+        assert_eq!(lines[1], r#""https://example.com/dir/a,%22b%22.txt","a,""b"".txt",42,2024-01-01T10:00:00+00:00,text/plain,,,text/plain; charset=utf-8,false,"""e1""""#);
+    }
+
+    #[test]
+    fn test_human_size () {
+        assert_eq!(human_size(0), "0");
+        assert_eq!(human_size(1023), "1023");
+        assert_eq!(human_size(512 * 1024), "512K");
+        assert_eq!(human_size(1536 * 1024), "1.5M");
+        assert_eq!(human_size(4294967296), "4.0G");
+        assert_eq!(human_size(1024 * 1024 - 1), "1.0M");
+        assert_eq!(human_size(u64::MAX), "16E");
+    }
+
+    #[test]
+    fn test_parse_format () {
+        for format in [OutputFormat::Human, OutputFormat::Json, OutputFormat::Csv, OutputFormat::Ndjson] {
+            assert_eq!(OutputFormat::parse(&format.to_string()), Some(format));
+        }
+        assert_eq!(OutputFormat::parse("xml"), None);
+    }
+}
@@ -0,0 +1,72 @@
+/**
+ * Defines the Spinner, an activity indicator on stderr for requests
+ * that may take a while, like PROPFINDs on big collections.
+ *
+ * (c) 2024 Andreas Feldner
+ */
+use std::io::{stderr, IsTerminal, Write};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+/// Fast operations finish before the spinner shows up, so they don't flicker
+const INITIAL_DELAY: Duration = Duration::from_millis(300);
+
+/// Shows a spinner with a message on stderr until dropped. It is not
+/// shown at all, if disabled or if stderr is not a terminal.
+pub struct Spinner {
+    // dropping the sender stops the spinner thread
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>
+}
+
+impl Spinner {
+    pub fn start(message: &str, enabled: bool) -> Spinner {
+        if !enabled || !stderr().is_terminal() {
+            return Spinner {stop: None, handle: None};
+        }
+        let (stop, stopped) = channel::<()>();
+        let message = message.to_string();
+        let handle = thread::spawn(move || {
+            if stopped.recv_timeout(INITIAL_DELAY) != Err(RecvTimeoutError::Timeout) {
+                return;
+            }
+            for frame in FRAMES.iter().cycle() {
+                eprint!("\r{frame} {message}");
+                let _ = stderr().flush();
+                if stopped.recv_timeout(FRAME_INTERVAL) != Err(RecvTimeoutError::Timeout) {
+                    break;
+                }
+            }
+            // clear the line, so results are printed on a clean terminal
+            eprint!("\r{}\r", " ".repeat(message.chars().count() + 2));
+            let _ = stderr().flush();
+        });
+        Spinner {stop: Some(stop), handle: Some(handle)}
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_spinner_stops_without_delay () {
+        let start = Instant::now();
+        drop(Spinner::start("Listing", true));
+        drop(Spinner::start("Listing", false));
+        assert!(start.elapsed() < INITIAL_DELAY);
+    }
+}
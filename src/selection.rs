@@ -0,0 +1,200 @@
+/**
+ * Defines the PathSelection object that decides which entries of a
+ * recursive walk are included, by rsync-style include/exclude globs.
+ *
+ * (c) 2024 Andreas Feldner
+ */
+use std::collections::VecDeque;
+use derive_more::Display;
+use glob::{MatchOptions, Pattern, PatternError};
+
+#[derive(Debug, Display)]
+pub enum SelectionError {
+    #[display(fmt = "invalid pattern {_0}: {_1}")]
+    InvalidPattern(String, PatternError),
+    #[display(fmt = "option {_0} requires a pattern")]
+    MissingPattern(String)
+}
+
+impl std::error::Error for SelectionError {}
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false
+};
+
+/// A compiled include or exclude pattern.
+#[derive(Debug)]
+struct Rule {
+    pattern: Pattern,
+    /// the pattern contains a `/`, so it is matched against the whole relative path
+    anchored: bool,
+    /// the pattern ends in `/`, so it only matches directories
+    directory_only: bool
+}
+
+impl Rule {
+    fn new(pattern_desc: &str) -> Result<Self, SelectionError> {
+        let directory_only = pattern_desc.ends_with('/');
+        let trimmed = pattern_desc.trim_end_matches('/');
+        let pattern = Pattern::new(trimmed)
+            .map_err(|error| SelectionError::InvalidPattern(pattern_desc.to_string(), error))?;
+        Ok(Self {pattern, anchored: trimmed.contains('/'), directory_only})
+    }
+
+    /// Matches a path, or any directory it is contained in.
+    fn matches(&self, relative_path: &str) -> bool {
+        let is_directory = relative_path.ends_with('/');
+        let components: Vec<&str> = relative_path.trim_end_matches('/').split('/').collect();
+        (1..=components.len()).any(|count| {
+            if self.directory_only && count == components.len() && !is_directory {
+                return false;
+            }
+            let candidate = if self.anchored {components[..count].join("/")} else {components[count - 1].to_string()};
+            self.pattern.matches_with(&candidate, MATCH_OPTIONS)
+        })
+    }
+}
+
+/// Selects relative paths of a recursive walk by include and exclude patterns.
+///
+/// Paths are relative to the root of the walk, separated by `/`, and
+/// directories end in `/`. The patterns are evaluated in this order:
+/// 1. if any exclude pattern matches, the path is skipped;
+/// 2. if no include pattern is given, the path is selected;
+/// 3. otherwise it is selected if any include pattern matches.
+///
+/// A pattern matches a path, if it matches the path itself or any directory
+/// containing it, so excluding `thumbs/` skips the whole subtree. Patterns
+/// without a `/` are matched against single names, those containing a `/`
+/// against the path from the root of the walk. A trailing `/` restricts
+/// the pattern to directories.
+#[derive(Debug, Default)]
+pub struct PathSelection {
+    includes: Vec<Rule>,
+    excludes: Vec<Rule>
+}
+
+impl PathSelection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn include(&mut self, pattern: &str) -> Result<(), SelectionError> {
+        self.includes.push(Rule::new(pattern)?);
+        Ok(())
+    }
+
+    pub fn exclude(&mut self, pattern: &str) -> Result<(), SelectionError> {
+        self.excludes.push(Rule::new(pattern)?);
+        Ok(())
+    }
+
+    /// Removes all `--include <glob>` and `--exclude <glob>` options from
+    /// command arguments, compiling them in the order given.
+    pub fn take_options(args: &mut VecDeque<String>) -> Result<Self, SelectionError> {
+        let mut selection = Self::new();
+        let mut remaining = VecDeque::with_capacity(args.len());
+        while let Some(arg) = args.pop_front() {
+            let is_include = match arg.as_str() {
+                "--include" => true,
+                "--exclude" => false,
+                _ => {
+                    remaining.push_back(arg);
+                    continue;
+                }
+            };
+            let pattern = args.pop_front().ok_or(SelectionError::MissingPattern(arg))?;
+            if is_include {selection.include(&pattern)?} else {selection.exclude(&pattern)?};
+        }
+        *args = remaining;
+        Ok(selection)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.includes.is_empty() && self.excludes.is_empty()
+    }
+
+    /// Checks if an entry of the walk is selected.
+    pub fn selects(&self, relative_path: &str) -> bool {
+        if self.excludes.iter().any(|rule| rule.matches(relative_path)) {
+            return false;
+        }
+        self.includes.is_empty() || self.includes.iter().any(|rule| rule.matches(relative_path))
+    }
+
+    /// Checks if the walk needs to descend into a directory, i.e. it might
+    /// contain selected entries. Includes don't prevent descending, as they
+    /// may match entries further down.
+    pub fn descends_into(&self, relative_directory: &str) -> bool {
+        let directory = format!("{}/", relative_directory.trim_end_matches('/'));
+        !self.excludes.iter().any(|rule| rule.matches(&directory))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selection(includes: &[&str], excludes: &[&str]) -> PathSelection {
+        let mut selection = PathSelection::new();
+        for pattern in includes {
+            selection.include(pattern).unwrap();
+        }
+        for pattern in excludes {
+            selection.exclude(pattern).unwrap();
+        }
+        selection
+    }
+
+    #[test]
+    fn test_empty_selection_selects_all () {
+        let selection = selection(&[], &[]);
+        assert!(selection.is_empty());
+        assert!(selection.selects("a/b/c.txt"));
+        assert!(selection.descends_into("a/b"));
+    }
+
+    #[test]
+    fn test_include_by_name () {
+        let selection = selection(&["*.jpg"], &[]);
+        assert!(selection.selects("holiday.jpg"));
+        assert!(selection.selects("2024/summer/holiday.jpg"));
+        assert!(!selection.selects("2024/notes.txt"));
+        assert!(selection.descends_into("2024"));
+    }
+
+    #[test]
+    fn test_exclude_takes_precedence () {
+        let selection = selection(&["*.jpg"], &["thumbs/"]);
+        assert!(selection.selects("2024/holiday.jpg"));
+        assert!(!selection.selects("2024/thumbs/holiday.jpg"));
+        assert!(!selection.descends_into("2024/thumbs"));
+    }
+
+    #[test]
+    fn test_anchored_pattern () {
+        let selection = selection(&[], &["2024/*/raw"]);
+        assert!(!selection.selects("2024/summer/raw/a.jpg"));
+        assert!(selection.selects("2023/summer/raw/a.jpg"));
+        assert!(selection.selects("2024/raw/a.jpg"));
+    }
+
+    #[test]
+    fn test_take_options () {
+        let mut args: VecDeque<String> = ["--include", "*.jpg", "photos", "--exclude", "thumbs/", "target"]
+            .iter().map(|arg| arg.to_string()).collect();
+        let selection = PathSelection::take_options(&mut args).unwrap();
+        assert_eq!(args, vec!("photos", "target"));
+        assert!(selection.selects("a.jpg"));
+        assert!(!selection.selects("thumbs/a.jpg"));
+        let mut args: VecDeque<String> = VecDeque::from(vec!("--exclude".to_string()));
+        assert!(matches!(PathSelection::take_options(&mut args), Err(SelectionError::MissingPattern(_))));
+    }
+
+    #[test]
+    fn test_invalid_pattern () {
+        assert!(matches!(PathSelection::new().include("[a"), Err(SelectionError::InvalidPattern(_, _))));
+    }
+}
@@ -0,0 +1,176 @@
+/**
+ * Defines a minimal HTTP server that answers each incoming connection
+ * with a canned reply, so that the DAV controller can be tested without
+ * a real WebDAV server.
+ *
+ * (c) 2024 Andreas Feldner
+ */
+use crate::interactive::DavCmdController;
+use netrc::Netrc;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use url::Url;
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub enum Reply {
+    /// Sends the given bytes as the complete response
+    Raw(Vec<u8>),
+    /// Reads the request, then closes the connection without answering
+    Close
+}
+
+impl Reply {
+    pub fn status(code: u16, reason: &str, headers: &[(&str, &str)], body: &str) -> Reply {
+        let mut response = format!("HTTP/1.1 {code} {reason}\r\nContent-Length: {}\r\nConnection: close\r\n", body.len());
+        for (name, value) in headers {
+            response.push_str(&format!("{name}: {value}\r\n"));
+        }
+        response.push_str("\r\n");
+        response.push_str(body);
+        Reply::Raw(response.into_bytes())
+    }
+
+    /// A 207 Multi-Status answer of the given `<D:response>` elements, e.g.
+    /// made by `response`, which may use the `D:` prefix for DAV:.
+    pub fn multistatus(responses: &str) -> Reply {
+        Reply::multistatus_with(&[], responses)
+    }
+
+    /// A 207 Multi-Status answer like `multistatus`, with further headers.
+    pub fn multistatus_with(headers: &[(&str, &str)], responses: &str) -> Reply {
+        let multistatus = format!(r#"<?xml version="1.0" encoding="utf-8"?><D:multistatus xmlns:D="DAV:">{responses}</D:multistatus>"#);
+        let headers: Vec<(&str, &str)> = [("Content-Type", "application/xml")].into_iter().chain(headers.iter().copied()).collect();
+        Reply::status(207, "Multi-Status", &headers, &multistatus)
+    }
+
+    /// A 207 Multi-Status answer listing the resources, each given by its
+    /// href and whether it is a collection.
+    pub fn listing(members: &[(&str, bool)]) -> Reply {
+        let responses: String = members.iter().map(|(href, is_collection)| {
+            let resource_type = if *is_collection {"<D:collection/>"} else {""};
+            response(href, &format!("<D:resourcetype>{resource_type}</D:resourcetype>"))
+        }).collect();
+        Reply::multistatus(&responses)
+    }
+}
+
+/// The `<D:response>` element of a resource, with the properties found.
+pub fn response(href: &str, found: &str) -> String {
+    response_with_missing(href, found, "")
+}
+
+/// The `<D:response>` element of a resource, with the properties found,
+/// and those not found, if any, with status 404.
+pub fn response_with_missing(href: &str, found: &str, missing: &str) -> String {
+    let mut response = format!("<D:response><D:href>{href}</D:href><D:propstat><D:prop>{found}</D:prop>\
+        <D:status>HTTP/1.1 200 OK</D:status></D:propstat>");
+    if !missing.is_empty() {
+        response.push_str(&format!("<D:propstat><D:prop>{missing}</D:prop><D:status>HTTP/1.1 404 Not Found</D:status></D:propstat>"));
+    }
+    response.push_str("</D:response>");
+    response
+}
+
+/// A command controller connected to the URL, as with `connect <url>`.
+pub fn connected_controller(url: &str) -> DavCmdController {
+    let mut controller = DavCmdController::new(Netrc::default());
+    controller.execute_command(&format!("connect {url}")).unwrap();
+    controller
+}
+
+pub struct TestServer {
+    pub url: Url,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+    handle: Option<JoinHandle<()>>
+}
+
+impl TestServer {
+    /// Starts a server answering one connection per given reply, in order.
+    pub fn start(replies: Vec<Reply>) -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+        let handle = thread::spawn(move || {
+            for reply in replies {
+                let Ok((mut stream, _)) = listener.accept() else { return };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                if let Some(request) = Self::_read_request(&mut reader) {
+                    recorded.lock().unwrap().push(request);
+                }
+                if let Reply::Raw(bytes) = reply {
+                    let _ = stream.write_all(&bytes);
+                    let _ = stream.flush();
+                }
+            }
+        });
+        TestServer {url, requests, handle: Some(handle)}
+    }
+
+    /// Waits until all replies have been sent and returns the requests received.
+    pub fn finish(mut self) -> Vec<RecordedRequest> {
+        if let Some(handle) = self.handle.take() {
+            handle.join().unwrap();
+        }
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn _read_request(reader: &mut impl BufRead) -> Option<RecordedRequest> {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let mut request_line = line.split_whitespace();
+        let method = request_line.next()?.to_string();
+        let path = request_line.next()?.to_string();
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            reader.read_line(&mut line).ok()?;
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            let (name, value) = header.split_once(':')?;
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+        let mut request = RecordedRequest {method, path, headers, body: Vec::new()};
+        if let Some(length) = request.header("content-length") {
+            let mut body = vec![0; length.parse().ok()?];
+            reader.read_exact(&mut body).ok()?;
+            request.body = body;
+        } else if request.header("transfer-encoding").is_some_and(|coding| coding.contains("chunked")) {
+            loop {
+                line.clear();
+                reader.read_line(&mut line).ok()?;
+                let chunk_size = usize::from_str_radix(line.trim(), 16).ok()?;
+                let mut chunk = vec![0; chunk_size + 2];
+                reader.read_exact(&mut chunk).ok()?;
+                if chunk_size == 0 {
+                    break;
+                }
+                request.body.extend_from_slice(&chunk[..chunk_size]);
+            }
+        }
+        Some(request)
+    }
+}
@@ -0,0 +1,259 @@
+/**
+ * Defines adapters for the data streams transferred to and from
+ * DAV servers.
+ *
+ * (c) 2024 Andreas Feldner
+ */
+use std::fs::File;
+use derive_more::Display;
+use std::io::{stderr, Error as IoError, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Number of bytes between two progress reports
+const PROGRESS_INTERVAL: u64 = 1024 * 1024;
+
+/// How much of a stream is kept in memory, for sending it again
+pub const REPLAY_LIMIT: usize = 1024 * 1024;
+
+struct Replay<R> {
+    source: R,
+    kept: Vec<u8>,
+    limit: usize,
+    // more than the limit was read, so the part kept is not the beginning
+    exhausted: bool
+}
+
+/// A reader that keeps the beginning of what was read from its source in
+/// memory, up to a limit. This allows to repeat an upload of a stream that
+/// cannot be read twice, e.g. stdin, if the server refused it early on.
+pub struct ReplayReader<R> {
+    replay: Arc<Mutex<Replay<R>>>
+}
+
+/// The handle to get the complete content of a `ReplayReader` after it
+/// has been handed over (and maybe partially consumed).
+pub struct ReplayHandle<R> {
+    replay: Arc<Mutex<Replay<R>>>
+}
+
+impl<R: Read> ReplayReader<R> {
+    pub fn new(source: R, limit: usize) -> (ReplayReader<R>, ReplayHandle<R>) {
+        let replay = Arc::new(Mutex::new(Replay {source, kept: Vec::new(), limit, exhausted: false}));
+        (ReplayReader {replay: Arc::clone(&replay)}, ReplayHandle {replay})
+    }
+}
+
+impl<R: Read> Read for ReplayReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        let mut replay = self.replay.lock().map_err(|_| IoError::other("replay lock poisoned"))?;
+        let count = replay.source.read(buf)?;
+        if replay.kept.len() + count <= replay.limit {
+            replay.kept.extend_from_slice(&buf[..count]);
+        } else if !replay.exhausted {
+            replay.exhausted = true;
+            replay.kept = Vec::new();
+        }
+        Ok(count)
+    }
+}
+
+impl<R: Read> ReplayHandle<R> {
+    /// Writes the part kept and the rest of the source into a temporary
+    /// file and returns it, positioned at its start. Returns None if more
+    /// than the limit was read before, so the content is lost.
+    pub fn into_complete_file(self) -> Result<Option<File>, IoError> {
+        let mut replay = self.replay.lock().map_err(|_| IoError::other("replay lock poisoned"))?;
+        if replay.exhausted {
+            return Ok(None);
+        }
+        let mut file = tempfile::tempfile()?;
+        file.write_all(&replay.kept)?;
+        std::io::copy(&mut replay.source, &mut file)?;
+        file.flush()?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(Some(file))
+    }
+}
+
+/// The error a `LimitedReader` fails with, once its source exceeds the limit.
+#[derive(Debug, Display)]
+#[display(fmt = "response exceeds the limit of {limit} bytes")]
+pub struct LimitExceeded {
+    pub limit: u64
+}
+
+impl std::error::Error for LimitExceeded {}
+
+/// A reader that fails with `LimitExceeded` instead of delivering more
+/// than a given number of bytes, protecting against overly large responses.
+pub struct LimitedReader<R> {
+    source: R,
+    limit: u64,
+    remaining: u64
+}
+
+impl<R: Read> LimitedReader<R> {
+    pub fn new(source: R, limit: u64) -> LimitedReader<R> {
+        LimitedReader {source, limit, remaining: limit}
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        if self.remaining == 0 {
+            // the limit is only exceeded if there actually is more to read
+            return match self.source.read(&mut [0; 1])? {
+                0 => Ok(0),
+                _ => Err(IoError::other(LimitExceeded {limit: self.limit}))
+            };
+        }
+        let max_count = buf.len().min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        let count = self.source.read(&mut buf[..max_count])?;
+        self.remaining -= count as u64;
+        Ok(count)
+    }
+}
+
+/// Frames of the activity indicator shown while the size is unknown
+const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Describes the progress of a transfer: a bar, if the expected size is
+/// known, otherwise an activity indicator turning with each report.
+fn _progress_line(transferred: u64, expected: Option<u64>, report: usize) -> String {
+    const BAR_WIDTH: u64 = 30;
+    match expected.filter(|expected| *expected > 0) {
+        Some(expected) => {
+            let done = transferred.min(expected);
+            let filled = (done * BAR_WIDTH / expected) as usize;
+            format!("[{}{}] {transferred}/{expected} bytes ({}%)",
+                "#".repeat(filled), " ".repeat(BAR_WIDTH as usize - filled), done * 100 / expected)
+        },
+        None => format!("{} {transferred} bytes transferred", FRAMES[report % FRAMES.len()])
+    }
+}
+
+/// A reader that counts the bytes read from its source, optionally
+/// reporting the progress on stderr, if it is a terminal. The progress
+/// line is cleared at the end of the source, or when the reader is dropped.
+pub struct CountingReader<R> {
+    source: R,
+    count: Arc<AtomicU64>,
+    show_progress: bool,
+    expected: Option<u64>,
+    next_report: u64,
+    reports: usize,
+    // the length of the progress line on the terminal, if one is shown
+    shown: usize
+}
+
+impl<R: Read> CountingReader<R> {
+    /// Returns the reader and the counter, which can be read after the
+    /// reader has been handed over.
+    pub fn new(source: R, show_progress: bool) -> (CountingReader<R>, Arc<AtomicU64>) {
+        let count = Arc::new(AtomicU64::new(0));
+        let reader = CountingReader {
+            source,
+            count: Arc::clone(&count),
+            show_progress: show_progress && stderr().is_terminal(),
+            expected: None,
+            next_report: PROGRESS_INTERVAL,
+            reports: 0,
+            shown: 0
+        };
+        (reader, count)
+    }
+
+    /// Lets the progress show a bar towards the size, e.g. from the
+    /// Content-Length of a response, instead of just the bytes counted.
+    pub fn expecting(mut self, expected: Option<u64>) -> CountingReader<R> {
+        self.expected = expected;
+        self
+    }
+}
+
+impl<R> CountingReader<R> {
+    fn _clear_progress(&mut self) {
+        if self.shown > 0 {
+            eprint!("\r{}\r", " ".repeat(self.shown));
+            let _ = stderr().flush();
+            self.shown = 0;
+        }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        let count = self.source.read(buf)?;
+        let total = self.count.fetch_add(count as u64, Ordering::Relaxed) + count as u64;
+        if count == 0 {
+            self._clear_progress();
+        } else if self.show_progress && total >= self.next_report {
+            let line = _progress_line(total, self.expected, self.reports);
+            eprint!("\r{line}");
+            let _ = stderr().flush();
+            self.shown = self.shown.max(line.chars().count());
+            self.reports += 1;
+            self.next_report = total + PROGRESS_INTERVAL;
+        }
+        Ok(count)
+    }
+}
+
+impl<R> Drop for CountingReader<R> {
+    fn drop(&mut self) {
+        self._clear_progress();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_keeps_partially_read_stream () {
+        let (mut reader, handle) = ReplayReader::new(&b"0123456789"[..], 4);
+        let mut start = [0; 4];
+        reader.read_exact(&mut start).unwrap();
+        drop(reader);
+        let mut content = String::new();
+        handle.into_complete_file().unwrap().unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, "0123456789");
+    }
+
+    #[test]
+    fn test_replay_beyond_limit () {
+        let (mut reader, handle) = ReplayReader::new(&b"0123456789"[..], 4);
+        let mut start = [0; 5];
+        reader.read_exact(&mut start).unwrap();
+        assert!(handle.into_complete_file().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_counting_reader () {
+        let (mut reader, count) = CountingReader::new(&b"0123456789"[..], false);
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content).unwrap();
+        drop(reader);
+        assert_eq!(count.load(Ordering::Relaxed), 10);
+    }
+
+    #[test]
+    fn test_progress_line () {
+        assert_eq!(_progress_line(0, Some(100), 0), format!("[{}] 0/100 bytes (0%)", " ".repeat(30)));
+        assert_eq!(_progress_line(50, Some(100), 1), format!("[{}{}] 50/100 bytes (50%)", "#".repeat(15), " ".repeat(15)));
+        // servers may deliver more than announced
+        assert_eq!(_progress_line(120, Some(100), 2), format!("[{}] 120/100 bytes (100%)", "#".repeat(30)));
+        assert_eq!(_progress_line(2048, None, 1), "/ 2048 bytes transferred");
+        assert_eq!(_progress_line(2048, Some(0), 0), "| 2048 bytes transferred");
+    }
+
+    #[test]
+    fn test_limited_reader () {
+        let mut content = Vec::new();
+        LimitedReader::new(&b"0123456789"[..], 10).read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"0123456789");
+        let error = LimitedReader::new(&b"0123456789"[..], 9).read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.get_ref().and_then(|inner| inner.downcast_ref::<LimitExceeded>()).map(|exceeded| exceeded.limit), Some(9));
+    }
+}